@@ -10,17 +10,17 @@ use futures_util::future::LocalBoxFuture;
 use futures_util::{FutureExt, SinkExt};
 use scoped_tls::scoped_thread_local;
 use smallvec::SmallVec;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::fmt;
 use std::future::{poll_fn, Future};
 use std::rc::{Rc, Weak};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, OnceLock};
 use std::task::{Poll, Waker};
 use std::time::{Duration, Instant};
 use tracing::warn;
 use tracy_client::set_thread_name;
-use windows::System::IUserDeviceAssociationChangedEventArgs;
 use winit::event::{Event, StartCause};
 use winit::event_loop::{ControlFlow, EventLoop, EventLoopBuilder, EventLoopProxy, EventLoopWindowTarget};
 use winit::window::WindowId;
@@ -50,10 +50,57 @@ struct Timer {
     deadline: Instant,
 }
 
+/// Source of the current time for timers.
+enum Clock {
+    /// Wall-clock time.
+    System,
+    /// Virtual time, advanced manually (see `TestRuntime::advance`).
+    Virtual(Cell<Instant>),
+}
+
 struct AppState {
     windows: RefCell<HashMap<WindowId, Weak<dyn WindowHandler>>>,
     spawner: LocalSpawner,
     timers: RefCell<SmallVec<Timer, 4>>,
+    clock: Clock,
+}
+
+impl AppState {
+    fn new(spawner: LocalSpawner, clock: Clock) -> AppState {
+        AppState {
+            windows: RefCell::new(HashMap::new()),
+            spawner,
+            timers: RefCell::new(Default::default()),
+            clock,
+        }
+    }
+
+    fn now(&self) -> Instant {
+        match self.clock {
+            Clock::System => Instant::now(),
+            Clock::Virtual(ref now) => now.get(),
+        }
+    }
+
+    /// Wakes all timers whose deadline has passed.
+    fn wake_expired_timers(&self) {
+        let now = self.now();
+        let timers = &mut *self.timers.borrow_mut();
+        timers.sort_by_key(|t| t.deadline);
+        while let Some(timer) = timers.first() {
+            if timer.deadline <= now {
+                let timer = timers.remove(0);
+                timer.waker.wake();
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Returns the deadline of the next timer to expire.
+    fn next_deadline(&self) -> Option<Instant> {
+        self.timers.borrow().iter().map(|t| t.deadline).min()
+    }
 }
 
 scoped_thread_local!(static APP_STATE: AppState);
@@ -109,11 +156,23 @@ pub fn quit() {
     });
 }
 
+/// Returns the current time.
+///
+/// This is the wall-clock time, unless called within a `TestRuntime`, in which case this returns the virtual time.
+pub fn now() -> Instant {
+    if APP_STATE.is_set() {
+        APP_STATE.with(|state| state.now())
+    } else {
+        Instant::now()
+    }
+}
+
+/// Waits until the specified deadline.
 pub async fn wait_until(deadline: Instant) {
     let mut registered = false;
     poll_fn(move |cx| {
         APP_STATE.with(|state| {
-            if Instant::now() >= deadline {
+            if state.now() >= deadline {
                 return Poll::Ready(());
             } else if !registered {
                 // set waker
@@ -132,7 +191,7 @@ pub async fn wait_until(deadline: Instant) {
 
 /// Waits for the specified duration.
 pub async fn wait_for(duration: Duration) {
-    let deadline = Instant::now() + duration;
+    let deadline = now() + duration;
     wait_until(deadline).await;
 }
 
@@ -152,11 +211,7 @@ pub fn run(root_future: impl Future<Output = ()> + 'static) -> Result<(), anyhow
     let _event_loop_start_time = Instant::now();
 
     let mut local_pool = LocalPool::new();
-    let app_state = AppState {
-        windows: RefCell::new(HashMap::new()),
-        spawner: local_pool.spawner(),
-        timers: RefCell::new(Default::default()),
-    };
+    let app_state = AppState::new(local_pool.spawner(), Clock::System);
 
     let result = APP_STATE.set(&app_state, || {
         // Before the event loop starts, spawn the root future, and poll it
@@ -178,17 +233,7 @@ pub fn run(root_future: impl Future<Output = ()> + 'static) -> Result<(), anyhow
                                 StartCause::ResumeTimeReached { .. }
                                 | StartCause::WaitCancelled { .. }
                                 | StartCause::Poll => {
-                                    // wake all expired timers
-                                    let timers = &mut *state.timers.borrow_mut();
-                                    let now = Instant::now();
-                                    while let Some(timer) = timers.first() {
-                                        if timer.deadline <= now {
-                                            let timer = timers.remove(0);
-                                            timer.waker.wake();
-                                        } else {
-                                            break;
-                                        }
-                                    }
+                                    state.wake_expired_timers();
                                 }
                                 StartCause::Init => {}
                             }
@@ -218,10 +263,8 @@ pub fn run(root_future: impl Future<Output = ()> + 'static) -> Result<(), anyhow
 
                     // set control flow to wait until next timer expires, or wait until next
                     // event if there are no timers
-                    let timers = &mut **state.timers.borrow_mut();
-                    if !timers.is_empty() {
-                        timers.sort_by_key(|t| t.deadline);
-                        elwt.set_control_flow(ControlFlow::WaitUntil(timers[0].deadline));
+                    if let Some(deadline) = state.next_deadline() {
+                        elwt.set_control_flow(ControlFlow::WaitUntil(deadline));
                     } else {
                         elwt.set_control_flow(ControlFlow::Wait);
                     }
@@ -234,3 +277,149 @@ pub fn run(root_future: impl Future<Output = ()> + 'static) -> Result<(), anyhow
     AppGlobals::teardown();
     result
 }
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// TestRuntime
+
+/// A deterministic, single-threaded runtime for testing async element logic without an event loop.
+///
+/// Tasks are spawned with `application::spawn` as usual (within `enter`), or with `TestRuntime::spawn`.
+/// Timers (`wait_for`, `wait_until`) are driven by a virtual clock that only moves forward
+/// when calling `advance`.
+pub struct TestRuntime {
+    local_pool: LocalPool,
+    state: AppState,
+}
+
+impl Default for TestRuntime {
+    fn default() -> Self {
+        TestRuntime::new()
+    }
+}
+
+impl TestRuntime {
+    pub fn new() -> TestRuntime {
+        let local_pool = LocalPool::new();
+        let state = AppState::new(local_pool.spawner(), Clock::Virtual(Cell::new(Instant::now())));
+        TestRuntime { local_pool, state }
+    }
+
+    /// Calls the closure with this runtime set as the current application state.
+    ///
+    /// Within the closure, `application::spawn`, `application::now` and the timer functions use this runtime.
+    pub fn enter<R>(&self, f: impl FnOnce() -> R) -> R {
+        APP_STATE.set(&self.state, f)
+    }
+
+    /// Spawns a task on this runtime.
+    ///
+    /// The task doesn't make progress until `run_until_stalled` or `advance` is called.
+    pub fn spawn(&self, fut: impl Future<Output = ()> + 'static) -> AbortHandle {
+        self.enter(|| spawn(fut))
+    }
+
+    /// Runs all tasks until none of them can make progress without the clock advancing.
+    pub fn run_until_stalled(&mut self) {
+        let local_pool = &mut self.local_pool;
+        APP_STATE.set(&self.state, || local_pool.run_until_stalled());
+    }
+
+    /// Runs the specified future to completion, advancing the virtual clock to the next timer
+    /// deadline whenever all tasks are stalled.
+    ///
+    /// # Panics
+    ///
+    /// If the future is blocked and no timers are pending.
+    pub fn run_until<F: Future>(&mut self, fut: F) -> F::Output {
+        struct WakeFlag(AtomicBool);
+        impl ArcWake for WakeFlag {
+            fn wake_by_ref(arc_self: &Arc<Self>) {
+                arc_self.0.store(true, Ordering::Relaxed);
+            }
+        }
+
+        let flag = Arc::new(WakeFlag(AtomicBool::new(false)));
+        let waker = futures::task::waker(flag.clone());
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut fut = std::pin::pin!(fut);
+        loop {
+            flag.0.store(false, Ordering::Relaxed);
+            if let Poll::Ready(output) = self.enter(|| fut.as_mut().poll(&mut cx)) {
+                return output;
+            }
+            self.run_until_stalled();
+            if !flag.0.load(Ordering::Relaxed) {
+                let deadline = self
+                    .state
+                    .next_deadline()
+                    .expect("future is blocked and no timers are pending");
+                self.set_now(deadline.max(self.now()));
+            }
+        }
+    }
+
+    /// Returns the current virtual time.
+    pub fn now(&self) -> Instant {
+        self.state.now()
+    }
+
+    fn set_now(&mut self, now: Instant) {
+        let Clock::Virtual(ref clock) = self.state.clock else {
+            unreachable!()
+        };
+        clock.set(now);
+        self.state.wake_expired_timers();
+        self.run_until_stalled();
+    }
+
+    /// Advances the virtual clock by the specified duration.
+    ///
+    /// Timers are fired in deadline order, and tasks run until stalled after each of them,
+    /// so that timers registered in the meantime also fire if their deadline is within the interval.
+    pub fn advance(&mut self, duration: Duration) {
+        let target = self.now() + duration;
+        self.run_until_stalled();
+        while let Some(deadline) = self.state.next_deadline() {
+            if deadline > target {
+                break;
+            }
+            self.set_now(deadline.max(self.now()));
+        }
+        self.set_now(target);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timers_follow_virtual_clock() {
+        let mut rt = TestRuntime::new();
+        let ticks = Rc::new(Cell::new(0));
+        let start = rt.now();
+
+        rt.spawn({
+            let ticks = ticks.clone();
+            async move {
+                loop {
+                    wait_for(Duration::from_millis(100)).await;
+                    ticks.set(ticks.get() + 1);
+                }
+            }
+        });
+
+        rt.run_until_stalled();
+        assert_eq!(ticks.get(), 0);
+        rt.advance(Duration::from_millis(99));
+        assert_eq!(ticks.get(), 0);
+        rt.advance(Duration::from_millis(1));
+        assert_eq!(ticks.get(), 1);
+        rt.advance(Duration::from_millis(350));
+        assert_eq!(ticks.get(), 4);
+        assert_eq!(rt.now() - start, Duration::from_millis(450));
+
+        rt.run_until(wait_for(Duration::from_millis(50)));
+        assert_eq!(ticks.get(), 5);
+    }
+}
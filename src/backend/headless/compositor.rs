@@ -0,0 +1,91 @@
+//! Headless compositor implementation details
+
+use std::cell::{Cell, RefCell};
+
+use raw_window_handle::RawWindowHandle;
+use skia_safe as sk;
+use tracing::warn;
+
+use crate::backend::raster::create_raster_surface;
+use crate::backend::ApplicationBackend;
+use crate::compositor::ColorType;
+use crate::Size;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Headless drawable surface backend.
+pub(crate) struct DrawableSurface {
+    surface: sk::Surface,
+}
+
+impl DrawableSurface {
+    pub(crate) fn surface(&self) -> sk::Surface {
+        self.surface.clone()
+    }
+}
+
+/// Compositor layer.
+pub struct Layer {
+    format: ColorType,
+    size: Cell<Size>,
+    surface: RefCell<sk::Surface>,
+}
+
+impl Layer {
+    /// Resizes a surface layer.
+    ///
+    /// The contents of the surface are discarded.
+    pub(crate) fn set_surface_size(&self, size: Size) {
+        // skip if same size
+        if self.size.get() == size {
+            return;
+        }
+
+        // avoid resizing to zero width
+        if size.width as u32 == 0 || size.height as u32 == 0 {
+            return;
+        }
+
+        self.size.set(size);
+        self.surface.replace(create_raster_surface(size, self.format));
+    }
+
+    /// Waits for the specified surface to be ready for presentation.
+    ///
+    /// Raster surfaces are never presented, so this returns immediately.
+    pub(crate) fn wait_for_presentation(&self) {}
+
+    /// Creates a skia drawing context for the specified surface layer.
+    pub(crate) fn acquire_drawing_surface(&self) -> DrawableSurface {
+        DrawableSurface {
+            surface: self.surface.borrow().clone(),
+        }
+    }
+
+    /// Returns a snapshot of the current contents of the surface.
+    pub(crate) fn snapshot(&self) -> sk::Image {
+        self.surface.borrow_mut().image_snapshot()
+    }
+
+    /// Binds a composition layer to a window.
+    ///
+    /// Headless layers can't be displayed, so this does nothing.
+    pub(crate) unsafe fn bind_to_window(&self, _window: RawWindowHandle) {
+        warn!("headless backend: layers are not presented to windows");
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Compositor impl
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl ApplicationBackend {
+    /// Creates a surface layer.
+    pub(crate) fn create_surface_layer(&self, size: Size, format: ColorType) -> Layer {
+        Layer {
+            format,
+            size: Cell::new(size),
+            surface: RefCell::new(create_raster_surface(size, format)),
+        }
+    }
+}
@@ -0,0 +1,27 @@
+//! Headless implementation details
+//!
+//! Layers are backed by skia raster surfaces in system memory, and are never presented to a compositor.
+//! This is used to render the UI without a GPU or a windowing system (e.g. in tests or on CI machines).
+use std::time::Duration;
+
+pub(crate) use compositor::{DrawableSurface, Layer};
+mod compositor;
+
+/// Double-click time used when there's no platform setting to query.
+const DEFAULT_DOUBLE_CLICK_TIME: Duration = Duration::from_millis(500);
+
+#[derive(Clone)]
+pub struct ApplicationBackend {
+    _private: (),
+}
+
+impl ApplicationBackend {
+    pub(crate) fn new() -> ApplicationBackend {
+        ApplicationBackend { _private: () }
+    }
+
+    /// Returns the double click time.
+    pub(crate) fn double_click_time(&self) -> Duration {
+        DEFAULT_DOUBLE_CLICK_TIME
+    }
+}
@@ -0,0 +1,160 @@
+//! Linux compositor implementation details
+
+use std::cell::{Cell, RefCell};
+use std::num::NonZeroU32;
+use std::rc::Rc;
+
+use raw_window_handle::{HandleError, HasWindowHandle, RawWindowHandle, WindowHandle};
+use skia_safe as sk;
+use tracing::error;
+use tracy_client::span;
+
+use crate::backend::linux::{BackendInner, RawDisplay};
+use crate::backend::raster::create_raster_surface;
+use crate::backend::ApplicationBackend;
+use crate::compositor::ColorType;
+use crate::Size;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Raw window handle that can be passed to softbuffer.
+#[derive(Copy, Clone)]
+struct RawWindow(RawWindowHandle);
+
+impl HasWindowHandle for RawWindow {
+    fn window_handle(&self) -> Result<WindowHandle<'_>, HandleError> {
+        // SAFETY: the window outlives the layer bound to it (see `Layer::bind_to_window`)
+        Ok(unsafe { WindowHandle::borrow_raw(self.0) })
+    }
+}
+
+type PresentTarget = softbuffer::Surface<RawDisplay, RawWindow>;
+
+/// Copies the contents of a raster surface to the window.
+fn blit_to_window(surface: &mut sk::Surface, target: &mut PresentTarget) -> Result<(), softbuffer::SoftBufferError> {
+    let width = NonZeroU32::new(surface.width() as u32);
+    let height = NonZeroU32::new(surface.height() as u32);
+    let (Some(width), Some(height)) = (width, height) else {
+        return Ok(());
+    };
+    target.resize(width, height)?;
+    let mut buffer = target.buffer_mut()?;
+
+    // Softbuffer pixels are 0RGB in native-endian u32s, which is BGRA8888 in memory on little-endian machines.
+    let info = sk::ImageInfo::new(
+        (width.get() as i32, height.get() as i32),
+        sk::ColorType::BGRA8888,
+        sk::AlphaType::Opaque,
+        sk::ColorSpace::new_srgb(),
+    );
+    let row_bytes = info.min_row_bytes();
+    // SAFETY: reinterpreting a slice of u32 as bytes is always valid
+    let pixels =
+        unsafe { std::slice::from_raw_parts_mut(buffer.as_mut_ptr() as *mut u8, std::mem::size_of_val(&*buffer)) };
+    {
+        let _span = span!("skia: read_pixels");
+        // this converts from the surface format (e.g. linear RGBAF16) to sRGB BGRA8888
+        surface.read_pixels(&info, pixels, row_bytes, (0, 0));
+    }
+    buffer.present()
+}
+
+/// Linux drawable surface backend.
+pub(crate) struct DrawableSurface {
+    surface: sk::Surface,
+    target: Option<Rc<RefCell<PresentTarget>>>,
+}
+
+impl DrawableSurface {
+    pub(crate) fn surface(&self) -> sk::Surface {
+        self.surface.clone()
+    }
+
+    fn present(&mut self) {
+        let Some(ref target) = self.target else { return };
+        let _span = span!("softbuffer: present");
+        if let Err(err) = blit_to_window(&mut self.surface, &mut target.borrow_mut()) {
+            error!("failed to present surface: {err}");
+        }
+        if let Some(client) = tracy_client::Client::running() {
+            client.frame_mark();
+        }
+    }
+}
+
+impl Drop for DrawableSurface {
+    fn drop(&mut self) {
+        self.present();
+    }
+}
+
+/// Compositor layer.
+pub struct Layer {
+    app: Rc<BackendInner>,
+    format: ColorType,
+    size: Cell<Size>,
+    surface: RefCell<sk::Surface>,
+    /// Window surface the layer is presented to.
+    target: RefCell<Option<Rc<RefCell<PresentTarget>>>>,
+}
+
+impl Layer {
+    /// Resizes a surface layer.
+    ///
+    /// The contents of the surface are discarded.
+    pub(crate) fn set_surface_size(&self, size: Size) {
+        // skip if same size
+        if self.size.get() == size {
+            return;
+        }
+
+        // avoid resizing to zero width
+        if size.width as u32 == 0 || size.height as u32 == 0 {
+            return;
+        }
+
+        self.size.set(size);
+        self.surface.replace(create_raster_surface(size, self.format));
+    }
+
+    /// Waits for the specified surface to be ready for presentation.
+    ///
+    /// Presentation is synchronous with softbuffer, so this returns immediately.
+    pub(crate) fn wait_for_presentation(&self) {}
+
+    /// Creates a skia drawing context for the specified surface layer.
+    pub(crate) fn acquire_drawing_surface(&self) -> DrawableSurface {
+        DrawableSurface {
+            surface: self.surface.borrow().clone(),
+            target: self.target.borrow().clone(),
+        }
+    }
+
+    /// Binds a composition layer to a window.
+    ///
+    /// # Safety
+    ///
+    /// The window handle must be valid, and the window must outlive the layer.
+    pub(crate) unsafe fn bind_to_window(&self, window: RawWindowHandle) {
+        let target = PresentTarget::new(self.app.softbuffer_context(), RawWindow(window))
+            .expect("failed to create softbuffer surface");
+        self.target.replace(Some(Rc::new(RefCell::new(target))));
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Compositor impl
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl ApplicationBackend {
+    /// Creates a surface layer.
+    pub(crate) fn create_surface_layer(&self, size: Size, format: ColorType) -> Layer {
+        Layer {
+            app: self.0.clone(),
+            format,
+            size: Cell::new(size),
+            surface: RefCell::new(create_raster_surface(size, format)),
+            target: RefCell::new(None),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Linux (X11/Wayland) implementation details
+//!
+//! There's no GPU compositor integration: layers are skia raster surfaces that are blitted
+//! to the window with softbuffer when a drawable surface is released.
+use std::cell::OnceCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use raw_window_handle::{DisplayHandle, HandleError, HasDisplayHandle, RawDisplayHandle};
+
+use crate::application::with_event_loop_window_target;
+
+pub(crate) use compositor::{DrawableSurface, Layer};
+mod compositor;
+
+/// Double-click time.
+///
+/// There's no portable way to query it on Linux (it's a toolkit setting), so use the same default as GTK.
+const DOUBLE_CLICK_TIME: Duration = Duration::from_millis(400);
+
+/// Raw display handle that can be passed to softbuffer.
+#[derive(Copy, Clone)]
+pub(crate) struct RawDisplay(RawDisplayHandle);
+
+impl HasDisplayHandle for RawDisplay {
+    fn display_handle(&self) -> Result<DisplayHandle<'_>, HandleError> {
+        // SAFETY: the display connection is owned by the event loop, which outlives the application backend
+        Ok(unsafe { DisplayHandle::borrow_raw(self.0) })
+    }
+}
+
+pub(crate) struct BackendInner {
+    /// Softbuffer context, created on first use because it needs the display connection of the event loop.
+    softbuffer_context: OnceCell<softbuffer::Context<RawDisplay>>,
+}
+
+impl BackendInner {
+    /// Returns the softbuffer context.
+    ///
+    /// Must be called within the event loop.
+    pub(crate) fn softbuffer_context(&self) -> &softbuffer::Context<RawDisplay> {
+        self.softbuffer_context.get_or_init(|| {
+            let display = with_event_loop_window_target(|event_loop| {
+                event_loop
+                    .display_handle()
+                    .expect("failed to get the display handle")
+                    .as_raw()
+            });
+            softbuffer::Context::new(RawDisplay(display)).expect("failed to create softbuffer context")
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct ApplicationBackend(Rc<BackendInner>);
+
+impl ApplicationBackend {
+    pub(crate) fn new() -> ApplicationBackend {
+        ApplicationBackend(Rc::new(BackendInner {
+            softbuffer_context: OnceCell::new(),
+        }))
+    }
+
+    /// Returns the double click time.
+    pub(crate) fn double_click_time(&self) -> Duration {
+        DOUBLE_CLICK_TIME
+    }
+}
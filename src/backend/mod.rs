@@ -1,6 +1,19 @@
 //! Platform-specific implementations of certain types and functions.
 
-#[cfg(windows)]
+#[cfg(any(target_os = "linux", feature = "headless"))]
+mod raster;
+
+#[cfg(all(windows, not(feature = "headless")))]
 mod windows;
-#[cfg(windows)]
+#[cfg(all(windows, not(feature = "headless")))]
 pub use self::windows::*;
+
+#[cfg(all(target_os = "linux", not(feature = "headless")))]
+mod linux;
+#[cfg(all(target_os = "linux", not(feature = "headless")))]
+pub use self::linux::*;
+
+#[cfg(feature = "headless")]
+mod headless;
+#[cfg(feature = "headless")]
+pub use self::headless::*;
@@ -0,0 +1,26 @@
+//! Software rendering helpers shared by the backends that render on the CPU.
+
+use skia_safe as sk;
+
+use crate::compositor::ColorType;
+use crate::Size;
+
+/// Creates a raster surface with the specified size (in pixels) and format.
+pub(crate) fn create_raster_surface(size: Size, format: ColorType) -> sk::Surface {
+    let width = size.width as i32;
+    let height = size.height as i32;
+    assert!(width > 0 && height > 0, "surface layer cannot be zero-sized");
+
+    // Floating-point formats are assumed to hold linear values, like the swap chains of the windows backend.
+    let color_space = match format {
+        ColorType::RGBAF16 | ColorType::RGBAF32 => sk::ColorSpace::new_srgb_linear(),
+        _ => sk::ColorSpace::new_srgb(),
+    };
+    let image_info = sk::ImageInfo::new(
+        (width, height),
+        format.to_skia_color_type(),
+        sk::AlphaType::Premul,
+        color_space,
+    );
+    sk::surfaces::raster(&image_info, None, None).expect("failed to create raster surface")
+}
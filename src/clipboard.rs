@@ -0,0 +1,56 @@
+//! Access to the system clipboard.
+//!
+//! If the system clipboard can't be opened (e.g. when running without a display server), the clipboard falls back
+//! to a buffer local to the application, so that copy and paste still work between the elements of the application.
+use std::cell::RefCell;
+
+use copypasta::{ClipboardContext, ClipboardProvider};
+use tracing::warn;
+
+enum Clipboard {
+    System(ClipboardContext),
+    Local(String),
+}
+
+thread_local! {
+    static CLIPBOARD: RefCell<Option<Clipboard>> = const { RefCell::new(None) };
+}
+
+fn with_clipboard<R>(f: impl FnOnce(&mut Clipboard) -> R) -> R {
+    CLIPBOARD.with_borrow_mut(|clipboard| {
+        let clipboard = clipboard.get_or_insert_with(|| match ClipboardContext::new() {
+            Ok(ctx) => Clipboard::System(ctx),
+            Err(err) => {
+                warn!("system clipboard unavailable, using a local clipboard: {err}");
+                Clipboard::Local(String::new())
+            }
+        });
+        f(clipboard)
+    })
+}
+
+/// Returns the text in the clipboard, or `None` if the clipboard doesn't contain text.
+pub fn get_text() -> Option<String> {
+    with_clipboard(|clipboard| match clipboard {
+        Clipboard::System(ctx) => match ctx.get_contents() {
+            Ok(text) => Some(text),
+            Err(err) => {
+                warn!("failed to read the clipboard: {err}");
+                None
+            }
+        },
+        Clipboard::Local(text) => Some(text.clone()),
+    })
+}
+
+/// Puts text in the clipboard.
+pub fn set_text(text: String) {
+    with_clipboard(|clipboard| match clipboard {
+        Clipboard::System(ctx) => {
+            if let Err(err) = ctx.set_contents(text) {
+                warn!("failed to write to the clipboard: {err}");
+            }
+        }
+        Clipboard::Local(local) => *local = text,
+    })
+}
@@ -0,0 +1,180 @@
+//! Commands and keyboard shortcuts.
+//!
+//! A command is a named action (e.g. `"palette.open"`) with an optional default keyboard shortcut.
+//! Elements declare the commands they handle with `Element::add_command`. When a key is pressed, the window
+//! looks for a command bound to the key chord on the focused element and its ancestors, innermost first,
+//! before sending `KeyDown` to the focused element. The element that declared the command is first asked whether
+//! the command is enabled with a `CommandQuery` event, and if it is, receives a `Command` event.
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail};
+use keyboard_types::{Key, KeyboardEvent, Modifiers};
+
+/// Modifiers that are significant in shortcuts (lock keys are ignored).
+const SHORTCUT_MODIFIERS: Modifiers = Modifiers::CONTROL
+    .union(Modifiers::SHIFT)
+    .union(Modifiers::ALT)
+    .union(Modifiers::SUPER);
+
+/// Key chord that triggers a command.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Shortcut {
+    pub modifiers: Modifiers,
+    /// The key. Character keys are stored in lowercase.
+    pub key: Key,
+}
+
+fn normalize_key(key: Key) -> Key {
+    match key {
+        Key::Character(s) => Key::Character(s.to_lowercase()),
+        key => key,
+    }
+}
+
+impl Shortcut {
+    pub fn new(modifiers: Modifiers, key: Key) -> Shortcut {
+        Shortcut {
+            modifiers: modifiers & SHORTCUT_MODIFIERS,
+            key: normalize_key(key),
+        }
+    }
+
+    /// Returns whether a keyboard event triggers this shortcut.
+    pub fn matches(&self, event: &KeyboardEvent) -> bool {
+        event.modifiers & SHORTCUT_MODIFIERS == self.modifiers && normalize_key(event.key.clone()) == self.key
+    }
+}
+
+impl FromStr for Shortcut {
+    type Err = anyhow::Error;
+
+    /// Parses shortcuts like `Ctrl+Shift+P`, `Alt+F4` or `Ctrl++`.
+    fn from_str(s: &str) -> Result<Shortcut, anyhow::Error> {
+        let (modifier_names, key) = match s.strip_suffix("++") {
+            Some(rest) => (rest, "+"),
+            None => s.rsplit_once('+').unwrap_or(("", s)),
+        };
+
+        let mut modifiers = Modifiers::empty();
+        for name in modifier_names.split('+').map(str::trim).filter(|name| !name.is_empty()) {
+            modifiers |= match name.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => Modifiers::CONTROL,
+                "shift" => Modifiers::SHIFT,
+                "alt" | "option" => Modifiers::ALT,
+                "super" | "meta" | "cmd" | "win" => Modifiers::SUPER,
+                _ => bail!("unknown modifier `{name}` in shortcut `{s}`"),
+            };
+        }
+
+        let key = key.trim();
+        let key = match key.chars().count() {
+            0 => bail!("missing key in shortcut `{s}`"),
+            1 => Key::Character(key.to_string()),
+            _ => Key::from_str(key).map_err(|_| anyhow!("unknown key `{key}` in shortcut `{s}`"))?,
+        };
+        Ok(Shortcut::new(modifiers, key))
+    }
+}
+
+impl fmt::Display for Shortcut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (modifier, name) in [
+            (Modifiers::CONTROL, "Ctrl"),
+            (Modifiers::SHIFT, "Shift"),
+            (Modifiers::ALT, "Alt"),
+            (Modifiers::SUPER, "Super"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{name}+")?;
+            }
+        }
+        match self.key {
+            Key::Character(ref s) => write!(f, "{}", s.to_uppercase()),
+            ref key => write!(f, "{key}"),
+        }
+    }
+}
+
+/// A named action that can be triggered by a keyboard shortcut.
+#[derive(Clone, Debug)]
+pub struct Command {
+    /// Unique name of the command, e.g. `"palette.open"`.
+    pub name: &'static str,
+    /// Default shortcut, which can be overridden with `bind_shortcut`.
+    pub shortcut: Option<Shortcut>,
+}
+
+impl Command {
+    pub fn new(name: &'static str) -> Command {
+        Command { name, shortcut: None }
+    }
+
+    pub fn with_shortcut(mut self, shortcut: Shortcut) -> Command {
+        self.shortcut = Some(shortcut);
+        self
+    }
+
+    /// Returns the shortcut that triggers the command, taking the bindings set with `bind_shortcut` into account.
+    pub fn effective_shortcut(&self) -> Option<Shortcut> {
+        KEY_BINDINGS
+            .with_borrow(|bindings| bindings.get(self.name).cloned())
+            .unwrap_or_else(|| self.shortcut.clone())
+    }
+}
+
+thread_local! {
+    /// Shortcuts that override the default shortcuts of commands, by command name.
+    static KEY_BINDINGS: RefCell<HashMap<&'static str, Option<Shortcut>>> = RefCell::new(HashMap::new());
+}
+
+/// Overrides the shortcut of a command. `None` removes the shortcut of the command.
+pub fn bind_shortcut(command: &'static str, shortcut: Option<Shortcut>) {
+    KEY_BINDINGS.with_borrow_mut(|bindings| bindings.insert(command, shortcut));
+}
+
+/// Restores the default shortcut of a command.
+pub fn reset_shortcut(command: &str) {
+    KEY_BINDINGS.with_borrow_mut(|bindings| bindings.remove(command));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_match_shortcuts() {
+        let shortcut: Shortcut = "Ctrl+Shift+P".parse().unwrap();
+        assert_eq!(shortcut.to_string(), "Ctrl+Shift+P");
+
+        let event = KeyboardEvent {
+            key: Key::Character("P".to_string()),
+            modifiers: Modifiers::CONTROL | Modifiers::SHIFT | Modifiers::NUM_LOCK,
+            ..Default::default()
+        };
+        assert!(shortcut.matches(&event));
+        assert!(!"Ctrl+P".parse::<Shortcut>().unwrap().matches(&event));
+
+        assert_eq!("Alt+F4".parse::<Shortcut>().unwrap().key, Key::F4);
+        assert_eq!("Ctrl++".parse::<Shortcut>().unwrap().key, Key::Character("+".to_string()));
+        assert!("Hyper+A".parse::<Shortcut>().is_err());
+    }
+
+    #[test]
+    fn match_super_shortcuts() {
+        let shortcut: Shortcut = "Cmd+Z".parse().unwrap();
+        assert_eq!(shortcut, "Super+Z".parse().unwrap());
+        assert_eq!(shortcut.to_string(), "Super+Z");
+
+        let event = |modifiers| KeyboardEvent {
+            key: Key::Character("z".to_string()),
+            modifiers,
+            ..Default::default()
+        };
+        assert!(shortcut.matches(&event(Modifiers::SUPER)));
+        assert!(!shortcut.matches(&event(Modifiers::META)));
+        assert!(!shortcut.matches(&event(Modifiers::SUPER | Modifiers::SHIFT)));
+    }
+}
@@ -105,6 +105,14 @@ impl Layer {
         self.0.set_surface_size(size);
     }
 
+    /// Returns a snapshot of the contents of a surface layer.
+    ///
+    /// Only available with the headless backend, whose surfaces live in system memory.
+    #[cfg(feature = "headless")]
+    pub fn snapshot(&self) -> sk::Image {
+        self.0.snapshot()
+    }
+
     /// Binds a layer to a native window.
     pub unsafe fn bind_to_window(&self, window: RawWindowHandle) {
         self.0.bind_to_window(window)
@@ -13,9 +13,10 @@ use crate::compositor::DrawableSurface;
 use bitflags::bitflags;
 use futures_util::future::LocalBoxFuture;
 use futures_util::FutureExt;
-use kurbo::{Affine, Point, Vec2};
+use kurbo::{Affine, Point, Rect, Vec2};
 
-use crate::event::Event;
+use crate::command::Command;
+use crate::event::{DragData, Event, EventCtx};
 use crate::layout::{BoxConstraints, Geometry, IntrinsicSizes};
 use crate::window::WeakWindow;
 use crate::PaintCtx;
@@ -45,6 +46,33 @@ pub trait AttachedProperty: Any {
     {
         item.get::<Self>()
     }
+
+    fn unset(self, item: &dyn Visual)
+    where
+        Self: Sized,
+    {
+        item.unset::<Self>();
+    }
+}
+
+/// Order in which sibling elements are painted: elements with a higher z-index are painted on top of
+/// (and hit-tested before) their siblings. Siblings with the same z-index are painted in tree order.
+///
+/// The default is 0.
+pub struct ZIndex;
+
+impl AttachedProperty for ZIndex {
+    type Value = i32;
+}
+
+/// Returns the children of a visual in paint order.
+fn children_in_paint_order(visual: &dyn Visual) -> Vec<Rc<dyn Visual>> {
+    let mut children = visual.children();
+    if children.iter().any(|c| ZIndex.get(&**c).is_some()) {
+        // stable sort, keeps the tree order for equal z-indices
+        children.sort_by_key(|c| ZIndex.get(&**c).unwrap_or(0));
+    }
+    children
 }
 
 /// Wrapper over Rc<dyn Visual> that has PartialEq impl.
@@ -247,6 +275,17 @@ pub struct Element {
     transform: Cell<kurbo::Affine>,
     /// Layout: geometry (size and baseline) of this element.
     geometry: Cell<Geometry>,
+    /// Clip rectangle of the children, in local coordinates.
+    clip_rect: Cell<Option<Rect>>,
+    /// Layout: constraints passed to the last call to `layout`, or `None` if the element hasn't been laid out yet.
+    last_constraints: Cell<Option<BoxConstraints>>,
+    /// Whether this element was explicitly marked as a relayout boundary.
+    relayout_boundary: Cell<bool>,
+    /// Whether this element acted as a relayout boundary during the last layout.
+    ///
+    /// This is true if the element is explicitly marked as a boundary, or if it was laid out
+    /// with tight constraints (its size can't change without the parent changing the constraints).
+    is_relayout_boundary: Cell<bool>,
     /// TODO unused
     change_flags: Cell<ChangeFlags>,
     // List of child elements.
@@ -255,6 +294,16 @@ pub struct Element {
     name: RefCell<String>,
     /// Whether the element is focusable via tab-navigation.
     focusable: Cell<bool>,
+    /// Position of the element in the tab order (see `set_tab_index`).
+    tab_index: Cell<i32>,
+    /// Whether tab navigation is confined to the subtree of this element.
+    focus_scope: Cell<bool>,
+    /// Whether the element accepts text input, in which case the input method is enabled when it has the focus.
+    accepts_text_input: Cell<bool>,
+    /// Whether the element receives events in the capture phase.
+    capture_phase_events: Cell<bool>,
+    /// Commands handled by this element.
+    commands: RefCell<Vec<Command>>,
 
     attached_properties: RefCell<BTreeMap<TypeId, Box<dyn Any>>>,
     // self-referential
@@ -281,9 +330,18 @@ impl Element {
             parent: Default::default(),
             transform: Cell::new(kurbo::Affine::default()),
             geometry: Cell::new(Geometry::default()),
+            clip_rect: Cell::new(None),
+            last_constraints: Cell::new(None),
+            relayout_boundary: Cell::new(false),
+            is_relayout_boundary: Cell::new(false),
             change_flags: Cell::new(ChangeFlags::LAYOUT | ChangeFlags::PAINT),
             name: RefCell::new(format!("{:p}", weak_this.as_ptr())),
             focusable: Cell::new(false),
+            tab_index: Cell::new(0),
+            focus_scope: Cell::new(false),
+            accepts_text_input: Cell::new(false),
+            capture_phase_events: Cell::new(false),
+            commands: RefCell::new(Vec::new()),
             attached_properties: Default::default(),
         }
     }
@@ -291,6 +349,10 @@ impl Element {
 
     /// Detaches this element from the tree.
     pub fn detach(&self) {
+        if self.parent.get().is_some() {
+            self.window.borrow().element_detached(self);
+        }
+
         // this.prev.next = this.next
         // OR this.parent.first_child = this.next
         if let Some(prev) = self.prev.upgrade() {
@@ -335,6 +397,7 @@ impl Element {
         self.next.set(Some(to_insert.rc()));
         // ins.parent = this.parent
         to_insert.parent.set(self.parent.get());
+        to_insert.set_parent_window(self.window.borrow().clone());
 
         if let Some(parent) = self.parent() {
             parent.mark_needs_relayout();
@@ -367,6 +430,7 @@ impl Element {
         }
         self.last_child.set(Some(child.weak()));
         child.parent.set(Some(self.weak()));
+        child.set_parent_window(self.window.borrow().clone());
         self.mark_needs_relayout()
     }
 
@@ -389,16 +453,66 @@ impl Element {
         })
     }
 
-    /// Returns the next focusable element.
-    pub fn next_focusable_element(&self) -> Option<Rc<dyn Visual>> {
-        let mut cursor = self.cursor();
-        cursor.next();      // skip self
-        while let Some(node) = cursor.next() {
-            if node.focusable.get() {
-                return Some(node);
+    /// Returns the focusable elements of the subtree rooted at this element (including itself), in tab order.
+    ///
+    /// Elements with a positive tab index come first, by increasing index, followed by the elements with
+    /// a zero tab index in tree order. Elements with a negative tab index are skipped.
+    pub fn tab_order(&self) -> Vec<Rc<dyn Visual>> {
+        fn collect(visual: Rc<dyn Visual>, order: &mut Vec<Rc<dyn Visual>>) {
+            if visual.focusable.get() && visual.tab_index.get() >= 0 {
+                order.push(visual.clone());
             }
+            for child in visual.iter_children() {
+                collect(child, order);
+            }
+        }
+
+        let mut order = Vec::new();
+        collect(self.rc(), &mut order);
+        // stable sort, keeps the tree order for equal indices
+        order.sort_by_key(|v| match v.tab_index.get() {
+            0 => i32::MAX,
+            index => index,
+        });
+        order
+    }
+
+    /// Returns the focus scope of this element: the nearest ancestor (or itself) marked with `set_focus_scope`,
+    /// or the root of the tree.
+    pub fn focus_scope(&self) -> Rc<dyn Visual> {
+        let mut current = self.rc();
+        while !current.focus_scope.get() {
+            let Some(parent) = current.parent() else { break };
+            current = parent;
         }
-        None
+        current
+    }
+
+    /// Returns the element after (or before) this element in the tab order of its focus scope, wrapping around.
+    fn focusable_element_in_direction(&self, forward: bool) -> Option<Rc<dyn Visual>> {
+        let order = self.focus_scope().tab_order();
+        let len = order.len();
+        if len == 0 {
+            return None;
+        }
+        let position = order.iter().position(|v| addr_eq(Rc::as_ptr(v), self.weak_this.as_ptr()));
+        let index = match (position, forward) {
+            (Some(i), true) => (i + 1) % len,
+            (Some(i), false) => (i + len - 1) % len,
+            (None, true) => 0,
+            (None, false) => len - 1,
+        };
+        Some(order[index].clone())
+    }
+
+    /// Returns the next focusable element in the tab order of the focus scope of this element.
+    pub fn next_focusable_element(&self) -> Option<Rc<dyn Visual>> {
+        self.focusable_element_in_direction(true)
+    }
+
+    /// Returns the previous focusable element in the tab order of the focus scope of this element.
+    pub fn previous_focusable_element(&self) -> Option<Rc<dyn Visual>> {
+        self.focusable_element_in_direction(false)
     }
 
     /// Returns an iterator over this element's children.
@@ -417,10 +531,77 @@ impl Element {
         self.focusable.set(focusable);
     }
 
+    /// Sets the position of this element in the tab order.
+    ///
+    /// Elements with a positive index come first, by increasing index, then elements with a zero index
+    /// (the default) in tree order. Elements with a negative index are skipped by tab navigation, but can still
+    /// get the focus with `set_focus`.
+    pub fn set_tab_index(&self, index: i32) {
+        self.tab_index.set(index);
+    }
+
+    pub fn tab_index(&self) -> i32 {
+        self.tab_index.get()
+    }
+
+    /// Makes this element a focus scope: when the focus is inside, tab navigation cycles through the focusable
+    /// elements of this subtree and doesn't leave it (e.g. for popups and dialogs).
+    pub fn set_focus_scope(&self, scope: bool) {
+        self.focus_scope.set(scope);
+    }
+
+    pub fn is_focus_scope(&self) -> bool {
+        self.focus_scope.get()
+    }
+
+    /// Sets whether this element receives the events targeting its descendants before them.
+    ///
+    /// In the capture phase, the event goes from the root down to the parent of the target.
+    /// See `EventCtx::phase`.
+    pub fn set_capture_phase_events(&self, enabled: bool) {
+        self.capture_phase_events.set(enabled);
+    }
+
+    pub fn receives_capture_phase_events(&self) -> bool {
+        self.capture_phase_events.get()
+    }
+
+    /// Declares a command handled by this element.
+    ///
+    /// The shortcut of the command is active while this element or one of its descendants has the focus.
+    /// The element receives `CommandQuery` events to tell whether the command is enabled, and `Command` events
+    /// to run it.
+    pub fn add_command(&self, command: Command) {
+        let mut commands = self.commands.borrow_mut();
+        commands.retain(|c| c.name != command.name);
+        commands.push(command);
+    }
+
+    pub fn remove_command(&self, name: &str) {
+        self.commands.borrow_mut().retain(|c| c.name != name);
+    }
+
+    /// Returns the first command declared by this element that satisfies the predicate.
+    pub(crate) fn find_command(&self, predicate: impl Fn(&Command) -> bool) -> Option<Command> {
+        self.commands.borrow().iter().find(|c| predicate(c)).cloned()
+    }
+
     pub fn set_pointer_capture(&self) {
         self.window.borrow().set_pointer_capture(self);
     }
 
+    /// Starts a drag-and-drop operation with this element as the source.
+    ///
+    /// Typically called from a `PointerMove` handler once the pointer has moved far enough from where
+    /// the button was pressed. From then on, the pointer events are turned into `DragEnter`/`DragOver`/`DragLeave`
+    /// events sent to the elements under the pointer (possibly in other windows), until the button is released.
+    /// The preview visual, if any, is painted under the pointer during the drag.
+    ///
+    /// The element receives a `DragEnd` event when the operation ends.
+    pub fn start_drag(&self, data: DragData, preview: Option<Rc<dyn Visual>>) {
+        self.window.borrow().start_drag(self, data, preview);
+    }
+
     /*pub fn children(&self) -> Ref<[AnyVisual]> {
         Ref::map(self.children.borrow(), |v| v.as_slice())
     }*/
@@ -440,6 +621,60 @@ impl Element {
             .is_focused(self)
     }
 
+    /// Returns whether this element has the focus and the focus should be visibly indicated.
+    ///
+    /// Like `:focus-visible` in CSS: this is true when the focus was moved with the keyboard, and false when
+    /// it was set after a click.
+    pub fn has_focus_visible(&self) -> bool {
+        self.window.borrow().is_focus_visible(self)
+    }
+
+    /// Returns whether this element or one of its descendants has the focus.
+    pub fn has_focus_within(&self) -> bool {
+        self.window.borrow().is_focus_within(self)
+    }
+
+    /// Sets whether this element accepts text input.
+    ///
+    /// The input method (IME) of the window is enabled while an element that accepts text input has the focus.
+    pub fn set_accepts_text_input(&self, accepts: bool) {
+        if self.accepts_text_input.replace(accepts) != accepts {
+            // enable or disable the input method if this element has the focus
+            self.window.borrow().accepts_text_input_changed(self);
+        }
+    }
+
+    pub fn accepts_text_input(&self) -> bool {
+        self.accepts_text_input.get()
+    }
+
+    /// Tells the input method where the caret is (in local coordinates), so that it can place its candidate
+    /// window next to it. Only has an effect if this element has the focus.
+    pub fn set_ime_cursor_area(&self, rect: Rect) {
+        self.window.borrow().set_ime_cursor_area(self, rect);
+    }
+
+    /// Scrolls the enclosing scroll views so that `rect`, in local coordinates, is visible.
+    ///
+    /// If the element belongs to a window, this happens after the next layout of the window, so that `rect` can be
+    /// computed from a geometry that is not up-to-date yet.
+    pub fn scroll_into_view(&self, rect: Rect) {
+        if !self.window.borrow().scroll_into_view(self.weak_this.clone(), rect) {
+            self.scroll_into_view_now(rect);
+        }
+    }
+
+    /// Asks the ancestors of this element, innermost first, to scroll so that `rect` is visible.
+    pub(crate) fn scroll_into_view_now(&self, rect: Rect) {
+        let mut rect = rect;
+        let mut current = self.rc();
+        while let Some(parent) = current.parent() {
+            rect = current.transform().transform_rect_bbox(rect);
+            rect = parent.scroll_rect_into_view(rect);
+            current = parent;
+        }
+    }
+
     /*/// Adds a child visual and sets its parent to this visual.
     // NOTE: pass `&Element` instead of `&dyn Visual` because deref-coercions seem to be more reliable
     // than unsized coercions to `&dyn Visual`.
@@ -454,6 +689,7 @@ impl Element {
     /// Removes all child visuals.
     pub fn clear_children(&self) {
         for c in self.iter_children() {
+            self.window.borrow().element_detached(&c);
             // TODO: don't do that if there's only one reference remaining
             // detach from window
             c.window.replace(WeakWindow::default());
@@ -510,6 +746,18 @@ impl Element {
         self.set_transform(Affine::translate(offset));
     }
 
+    /// Sets the rectangle, in local coordinates, outside of which children are neither painted nor hit-tested.
+    ///
+    /// This should be called by `Visual::layout()` so this doesn't set the layout dirty flag.
+    pub fn set_clip_rect(&self, clip_rect: Option<Rect>) {
+        self.clip_rect.set(clip_rect);
+    }
+
+    /// Returns the clip rectangle of the children of this visual.
+    pub fn clip_rect(&self) -> Option<Rect> {
+        self.clip_rect.get()
+    }
+
     /// Returns the transform from this visual's coordinate space to the coordinate space of the parent window.
     ///
     /// This walks up the parent chain and multiplies the transforms, so consider reusing the result instead
@@ -554,10 +802,22 @@ impl Element {
     }
 
     fn set_dirty_flags(&self, flags: ChangeFlags) {
-        let flags = self.change_flags.get() | flags;
+        let prev_flags = self.change_flags.get();
+        let flags = prev_flags | flags;
         self.change_flags.set(flags);
+
+        // Don't propagate the layout flag past relayout boundaries: the boundary is scheduled for
+        // relayout on its own, and since its size doesn't change, its parent doesn't need to be laid out again.
+        let mut parent_flags = flags;
+        if flags.contains(ChangeFlags::LAYOUT) && self.is_relayout_boundary.get() {
+            let scheduled = prev_flags.contains(ChangeFlags::LAYOUT)
+                || self.window.borrow().schedule_relayout(self.weak_this.clone());
+            if scheduled {
+                parent_flags.remove(ChangeFlags::LAYOUT);
+            }
+        }
         if let Some(parent) = self.parent() {
-            parent.set_dirty_flags(flags);
+            parent.set_dirty_flags(parent_flags);
         }
         if flags.contains(ChangeFlags::PAINT) {
             // TODO: maybe don't call repaint for every widget in the hierarchy. winit should coalesce repaint requests, but still
@@ -588,18 +848,60 @@ impl Element {
     pub fn needs_repaint(&self) -> bool {
         self.change_flags.get().contains(ChangeFlags::PAINT)
     }
+
+    /// Marks this element as a relayout boundary.
+    ///
+    /// Changes to the layout of the descendants of a relayout boundary only relayout the subtree
+    /// rooted at the boundary, instead of the whole window. Only use this on elements whose size
+    /// doesn't depend on their contents (e.g. a text editor with a fixed size). If the size
+    /// of the boundary ends up changing anyway, the parent is laid out again.
+    ///
+    /// Elements laid out with tight constraints are always treated as relayout boundaries.
+    pub fn set_relayout_boundary(&self, boundary: bool) {
+        self.relayout_boundary.set(boundary);
+        self.is_relayout_boundary
+            .set(boundary || self.last_constraints.get().map_or(false, |c| c.min == c.max));
+    }
+
+    /// Returns the constraints of the last layout of this element, or `None` if it hasn't been laid out yet.
+    pub fn last_constraints(&self) -> Option<BoxConstraints> {
+        self.last_constraints.get()
+    }
 }
 
 /// Nodes in the visual tree.
 pub trait Visual: EventTarget {
     fn element(&self) -> &Element;
 
+    /// Returns the min-content and max-content sizes of this element.
+    ///
+    /// `min.width` is the min-content width (the narrowest the element can be without its content overflowing,
+    /// e.g. the longest word of a text), and `max.width` is the max-content width (the width of the element given
+    /// unlimited space). The heights are the heights of the element at those widths.
+    ///
+    /// The default implementation returns the union of the intrinsic sizes of the children (like the default
+    /// `layout`, which stacks them).
     fn intrinsic_sizes(&self) -> IntrinsicSizes {
-        // TODO
-        IntrinsicSizes {
-            min: Default::default(),
-            max: Default::default(),
+        let mut isizes = IntrinsicSizes::default();
+        for child in self.element().iter_children() {
+            let s = child.intrinsic_sizes();
+            isizes.min.width = isizes.min.width.max(s.min.width);
+            isizes.min.height = isizes.min.height.max(s.min.height);
+            isizes.max.width = isizes.max.width.max(s.max.width);
+            isizes.max.height = isizes.max.height.max(s.max.height);
         }
+        isizes
+    }
+
+    /// Returns the height of this element when laid out with the specified width.
+    ///
+    /// This is used to determine the height of content-sized elements whose height depends on their width
+    /// (e.g. wrapped text).
+    fn intrinsic_height(&self, width: f64) -> f64 {
+        self.element()
+            .iter_children()
+            .map(|child| child.intrinsic_height(width))
+            .fold(0.0, f64::max)
     }
 
     // TODO: this could take a "SiblingIter"
@@ -620,13 +922,21 @@ pub trait Visual: EventTarget {
     fn hit_test(&self, point: Point) -> bool {
         self.element().geometry.get().size.to_rect().contains(point)
     }
+
+    /// Scrolls the content of this element, if it is scrollable, so that `rect` (in local coordinates) is visible.
+    ///
+    /// Returns the position of `rect` after scrolling. The default implementation doesn't scroll.
+    fn scroll_rect_into_view(&self, rect: Rect) -> Rect {
+        rect
+    }
+
     #[allow(unused_variables)]
     fn paint(&self, ctx: &mut PaintCtx) {}
 
     // Why async? this is because the visual may transfer control to async event handlers
     // before returning.
     #[allow(unused_variables)]
-    async fn event(&self, event: &mut Event)
+    async fn event(&self, ctx: &mut EventCtx, event: &mut Event)
     where
         Self: Sized,
     {
@@ -635,15 +945,15 @@ pub trait Visual: EventTarget {
 
 /// Implementation detail of `Visual` to get an object-safe version of `async fn event()`.
 trait EventTarget {
-    fn event_future<'a>(&'a self, event: &'a mut Event) -> LocalBoxFuture<'a, ()>;
+    fn event_future<'a>(&'a self, ctx: &'a mut EventCtx, event: &'a mut Event) -> LocalBoxFuture<'a, ()>;
 }
 
 impl<T> EventTarget for T
 where
     T: Visual,
 {
-    fn event_future<'a>(&'a self, event: &'a mut Event) -> LocalBoxFuture<'a, ()> {
-        self.event(event).boxed_local()
+    fn event_future<'a>(&'a self, ctx: &'a mut EventCtx, event: &'a mut Event) -> LocalBoxFuture<'a, ()> {
+        self.event(ctx, event).boxed_local()
     }
 }
 
@@ -702,6 +1012,14 @@ impl dyn Visual + '_ {
             .insert(TypeId::of::<T>(), Box::new(value));
     }
 
+    /// Removes the value of an attached property.
+    pub fn unset<T: AttachedProperty>(&self) {
+        self.element()
+            .attached_properties
+            .borrow_mut()
+            .remove(&TypeId::of::<T>());
+    }
+
     /// Gets the value of an attached property.
     pub fn get<T: AttachedProperty>(&self) -> Option<T::Value> {
         self.element()
@@ -716,10 +1034,17 @@ impl dyn Visual + '_ {
     }
 
     pub fn do_layout(&self, constraints: &BoxConstraints) -> Geometry {
-        let children = self.children();
+        if !self.needs_relayout() && self.last_constraints.get() == Some(*constraints) {
+            // the subtree is clean and the constraints haven't changed, reuse the last result
+            return self.geometry.get();
+        }
 
+        let children = self.children();
         let geometry = self.layout(&*children, constraints);
         self.geometry.set(geometry);
+        self.last_constraints.set(Some(*constraints));
+        self.is_relayout_boundary
+            .set(self.relayout_boundary.get() || constraints.min == constraints.max);
         self.mark_layout_done();
         geometry
     }
@@ -735,9 +1060,9 @@ impl dyn Visual + '_ {
         }
     }
 
-    pub async fn send_event(&self, event: &mut Event) {
+    pub async fn send_event(&self, ctx: &mut EventCtx, event: &mut Event) {
         // issue: allocating on every event is not great
-        self.event_future(event).await;
+        self.event_future(ctx, event).await;
     }
 
     /// Hit-tests this visual and its children.
@@ -753,16 +1078,22 @@ impl dyn Visual + '_ {
                 result.push(visual.rc().into());
             }
 
-            visual.traverse_children(|child| {
+            if let Some(clip_rect) = visual.clip_rect() {
+                if !clip_rect.contains(point) {
+                    return hit;
+                }
+            }
+
+            // topmost children first
+            for child in children_in_paint_order(visual).iter().rev() {
                 let transform = transform * child.transform();
-                let local_point = transform.inverse() * point;
-                if hit_test_rec(&*child, local_point, transform, result) {
+                // `point` is already in the local space of `visual`, only apply the child transform
+                let local_point = child.transform().inverse() * point;
+                if hit_test_rec(&**child, local_point, transform, result) {
                     hit = true;
-                    false
-                } else {
-                    true
+                    break;
                 }
-            });
+            }
             hit
         }
 
@@ -778,19 +1109,25 @@ impl dyn Visual + '_ {
             surface,
         };
 
-        // Recursively paint the UI tree.
-        fn paint_rec(visual: &dyn Visual, ctx: &mut PaintCtx) {
-            visual.paint(ctx);
-            for child in visual.iter_children() {
-                ctx.with_transform(&child.transform(), |ctx| {
-                    // TODO clipping
-                    paint_rec(&*child, ctx);
-                    child.mark_paint_done();
-                });
-            }
-        }
+        paint_subtree(self, &mut paint_ctx);
+    }
+}
 
-        paint_rec(self, &mut paint_ctx);
+/// Recursively paints a visual and its descendants.
+pub(crate) fn paint_subtree(visual: &dyn Visual, ctx: &mut PaintCtx) {
+    visual.paint(ctx);
+    let paint_children = |ctx: &mut PaintCtx| {
+        for child in children_in_paint_order(visual) {
+            ctx.with_transform(&child.transform(), |ctx| {
+                paint_subtree(&*child, ctx);
+                child.mark_paint_done();
+            });
+        }
+    };
+    if let Some(clip_rect) = visual.clip_rect() {
+        ctx.with_clip_rect(clip_rect, paint_children);
+    } else {
+        paint_children(ctx);
     }
 }
 
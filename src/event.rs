@@ -1,25 +1,40 @@
 //! Events sent to elements.
+use std::any::Any;
 use std::fmt;
+use std::path::PathBuf;
+use std::rc::Rc;
 
 pub use keyboard_types::KeyboardEvent;
 pub use keyboard_types::Modifiers;
 use kurbo::Vec2;
 use kurbo::{Affine, Point};
+use serde::{Deserialize, Serialize};
 
 mod key_code;
+mod recording;
 
 pub(crate) use key_code::key_event_to_key_code;
 pub(crate) use key_code::to_keyboard_type_modifiers;
+pub(crate) use recording::EventRecorder;
+pub use recording::{EventRecording, RecordedEvent, RecordedEventKind, RecordedPointerEvent};
 
 /// Represents the type of pointer.
-#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum PointerType {
+    #[default]
     Mouse,
     Pen,
     Stylus,
+    Touch,
     Other,
 }
 
+/// Identifier of the mouse pointer (touch pointers use the identifier of the touch).
+pub const MOUSE_POINTER_ID: u64 = 0;
+
+/// Scroll distance of one mouse wheel line, in device-independent pixels.
+pub const WHEEL_LINE_HEIGHT: f64 = 40.0;
+
 /// Represents a pointer button.
 // TODO why u no bitflags?
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
@@ -118,6 +133,10 @@ pub struct PointerEvent {
     pub transform: Affine,
     /// Whether the receiver has captured the pointer.
     pub request_capture: bool,
+    /// The type of device that emitted this event.
+    pub pointer_type: PointerType,
+    /// Identifies the pointer among simultaneously active pointers (e.g. the fingers of a multi-touch gesture).
+    pub pointer_id: u64,
 }
 
 impl PointerEvent {
@@ -147,6 +166,216 @@ impl PointerEvent {
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 
+/// Scroll amount of a wheel event.
+///
+/// Like in the DOM, positive values scroll the content towards the bottom (or the right).
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum WheelDelta {
+    /// Amount in lines, for mouse wheels.
+    Lines(Vec2),
+    /// Amount in device-independent (logical) pixels, for touchpads and high-resolution wheels.
+    ///
+    /// This is the same unit as the positions of pointer events, including the `pointer` of the wheel event
+    /// and touch positions.
+    Pixels(Vec2),
+}
+
+/// Mouse wheel or touchpad pan event.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct WheelEvent {
+    /// The state of the pointer (including the keyboard modifiers) when the wheel was moved.
+    pub pointer: PointerEvent,
+    pub delta: WheelDelta,
+}
+
+impl WheelEvent {
+    /// Returns the scroll amount in device-independent pixels.
+    pub fn pixel_delta(&self) -> Vec2 {
+        match self.delta {
+            WheelDelta::Lines(lines) => lines * WHEEL_LINE_HEIGHT,
+            WheelDelta::Pixels(pixels) => pixels,
+        }
+    }
+}
+
+/// Phase of a continuous gesture.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum GesturePhase {
+    Started,
+    Moved,
+    Ended,
+    Cancelled,
+}
+
+/// Touchpad pinch or rotation gesture.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct GestureEvent {
+    /// The state of the pointer when the gesture happened.
+    pub pointer: PointerEvent,
+    /// For pinch gestures, the change in scale factor (positive when zooming in).
+    /// For rotation gestures, the change in angle in degrees (positive when rotating counterclockwise).
+    pub delta: f64,
+    pub phase: GesturePhase,
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Payload of a drag-and-drop operation.
+///
+/// Holds a value of any type; drop targets check whether they can handle it with `DragData::get`.
+#[derive(Clone)]
+pub struct DragData(Rc<dyn Any>);
+
+impl DragData {
+    pub fn new<T: Any>(payload: T) -> DragData {
+        DragData(Rc::new(payload))
+    }
+
+    /// Returns the payload if it is of type `T`.
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.0.downcast_ref()
+    }
+
+    /// Returns whether the payload is of type `T`.
+    pub fn is<T: Any>(&self) -> bool {
+        self.0.is::<T>()
+    }
+}
+
+impl fmt::Debug for DragData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DragData(..)")
+    }
+}
+
+/// Drag-and-drop event.
+#[derive(Clone, Debug)]
+pub struct DragEvent {
+    /// The state of the pointer dragging the payload.
+    pub pointer: PointerEvent,
+    pub data: DragData,
+    /// On `DragOver`, drop targets set this to `true` to accept the payload.
+    ///
+    /// On `DragEnd`, tells the drag source whether the payload has been dropped on a target.
+    pub accepted: bool,
+}
+
+impl DragEvent {
+    /// Accepts the payload: if the pointer is released over the target, it will receive a `Drop` event.
+    pub fn accept(&mut self) {
+        self.accepted = true;
+    }
+}
+
+/// File dragged over a window from outside the application (e.g. from a file manager).
+#[derive(Clone, Debug)]
+pub struct FileDragEvent {
+    /// The state of the pointer.
+    pub pointer: PointerEvent,
+    pub path: PathBuf,
+}
+
+/// Query for the enabled state of a command.
+#[derive(Clone, Debug)]
+pub struct CommandQuery {
+    /// Name of the command.
+    pub command: &'static str,
+    /// Whether the command is enabled. `true` unless the element sets it to `false`.
+    pub enabled: bool,
+}
+
+/// Input method (IME) event.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ImeEvent {
+    /// The input method was enabled.
+    Enabled,
+    /// The text being composed changed.
+    ///
+    /// The text should be displayed at the caret, with the specified byte range of the text highlighted as the
+    /// cursor of the composition. An empty text means that the composition ended.
+    Preedit(String, Option<(usize, usize)>),
+    /// The composition is finished: the text should be inserted at the caret.
+    Commit(String),
+    /// The input method was disabled.
+    Disabled,
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Phase of the dispatch of an event.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum EventPhase {
+    /// The event is travelling from the root down to the parent of the target.
+    ///
+    /// Only elements that opted in with `Element::set_capture_phase_events` receive events in this phase.
+    Capturing,
+    /// The event is delivered to the target.
+    AtTarget,
+    /// The event is travelling from the parent of the target up to the root.
+    Bubbling,
+}
+
+/// State of the dispatch of an event, passed to event handlers along with the event.
+#[derive(Clone, Debug)]
+pub struct EventCtx {
+    pub(crate) phase: EventPhase,
+    handled: bool,
+    propagation_stopped: bool,
+    default_prevented: bool,
+}
+
+impl EventCtx {
+    pub(crate) fn new() -> EventCtx {
+        EventCtx {
+            phase: EventPhase::AtTarget,
+            handled: false,
+            propagation_stopped: false,
+            default_prevented: false,
+        }
+    }
+
+    /// Returns the current dispatch phase.
+    pub fn phase(&self) -> EventPhase {
+        self.phase
+    }
+
+    /// Marks the event as handled.
+    ///
+    /// This stops the propagation of the event and prevents its default action.
+    pub fn set_handled(&mut self) {
+        self.handled = true;
+        self.propagation_stopped = true;
+        self.default_prevented = true;
+    }
+
+    /// Returns whether an event handler has marked the event as handled.
+    pub fn is_handled(&self) -> bool {
+        self.handled
+    }
+
+    /// Stops the event from being delivered to the next elements in the dispatch chain.
+    ///
+    /// Unlike `set_handled`, this doesn't prevent the default action of the event.
+    pub fn stop_propagation(&mut self) {
+        self.propagation_stopped = true;
+    }
+
+    pub fn is_propagation_stopped(&self) -> bool {
+        self.propagation_stopped
+    }
+
+    /// Prevents the default action of the window in response to the event (e.g. moving the focus on Tab).
+    pub fn prevent_default(&mut self) {
+        self.default_prevented = true;
+    }
+
+    pub fn is_default_prevented(&self) -> bool {
+        self.default_prevented
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
 /*/// Keyboard event.
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub struct KeyboardEvent {
@@ -165,7 +394,10 @@ pub struct KeyboardEvent {
 pub enum Event {
     FocusGained,
     FocusLost,
-    MenuCommand(usize),
+    /// Asks the element whether a command that it declared is enabled.
+    CommandQuery(CommandQuery),
+    /// Runs a command declared by the element.
+    Command(&'static str),
     PointerMove(PointerEvent),
     PointerUp(PointerEvent),
     PointerDown(PointerEvent),
@@ -173,8 +405,29 @@ pub enum Event {
     PointerOut(PointerEvent),
     PointerEnter(PointerEvent),
     PointerLeave(PointerEvent),
+    Wheel(WheelEvent),
+    Pinch(GestureEvent),
+    Rotate(GestureEvent),
+    /// A drag-and-drop operation entered the element.
+    DragEnter(DragEvent),
+    /// The pointer moved over the element during a drag-and-drop operation.
+    DragOver(DragEvent),
+    /// A drag-and-drop operation left the element.
+    DragLeave(DragEvent),
+    /// The payload was dropped on the element.
+    Drop(DragEvent),
+    /// Sent to the element that started the drag-and-drop operation when it ends.
+    DragEnd(DragEvent),
+    /// Files dragged from outside the application are over the element. Sent once for each file.
+    FileHovered(FileDragEvent),
+    /// A file dragged from outside the application was dropped on the element. Sent once for each file.
+    FileDropped(FileDragEvent),
+    /// The files dragged over the element left the window, or the drag was cancelled.
+    FileHoverCancelled(PointerEvent),
     KeyDown(KeyboardEvent),
     KeyUp(KeyboardEvent),
+    /// Input method event, sent to the focused element.
+    Ime(ImeEvent),
 }
 
 impl Event {
@@ -186,7 +439,18 @@ impl Event {
             | Event::PointerOver(ref mut pe)
             | Event::PointerOut(ref mut pe)
             | Event::PointerEnter(ref mut pe)
-            | Event::PointerLeave(ref mut pe) => {
+            | Event::PointerLeave(ref mut pe)
+            | Event::Wheel(WheelEvent { pointer: ref mut pe, .. })
+            | Event::Pinch(GestureEvent { pointer: ref mut pe, .. })
+            | Event::Rotate(GestureEvent { pointer: ref mut pe, .. })
+            | Event::DragEnter(DragEvent { pointer: ref mut pe, .. })
+            | Event::DragOver(DragEvent { pointer: ref mut pe, .. })
+            | Event::DragLeave(DragEvent { pointer: ref mut pe, .. })
+            | Event::Drop(DragEvent { pointer: ref mut pe, .. })
+            | Event::DragEnd(DragEvent { pointer: ref mut pe, .. })
+            | Event::FileHovered(FileDragEvent { pointer: ref mut pe, .. })
+            | Event::FileDropped(FileDragEvent { pointer: ref mut pe, .. })
+            | Event::FileHoverCancelled(ref mut pe) => {
                 let prev = pe.transform;
                 pe.transform *= *transform;
                 Some(prev)
@@ -203,7 +467,18 @@ impl Event {
             | Event::PointerOver(ref mut pe)
             | Event::PointerOut(ref mut pe)
             | Event::PointerEnter(ref mut pe)
-            | Event::PointerLeave(ref mut pe) => {
+            | Event::PointerLeave(ref mut pe)
+            | Event::Wheel(WheelEvent { pointer: ref mut pe, .. })
+            | Event::Pinch(GestureEvent { pointer: ref mut pe, .. })
+            | Event::Rotate(GestureEvent { pointer: ref mut pe, .. })
+            | Event::DragEnter(DragEvent { pointer: ref mut pe, .. })
+            | Event::DragOver(DragEvent { pointer: ref mut pe, .. })
+            | Event::DragLeave(DragEvent { pointer: ref mut pe, .. })
+            | Event::Drop(DragEvent { pointer: ref mut pe, .. })
+            | Event::DragEnd(DragEvent { pointer: ref mut pe, .. })
+            | Event::FileHovered(FileDragEvent { pointer: ref mut pe, .. })
+            | Event::FileDropped(FileDragEvent { pointer: ref mut pe, .. })
+            | Event::FileHoverCancelled(ref mut pe) => {
                 pe.transform = *transform;
             }
             _ => {}
@@ -218,11 +493,47 @@ impl Event {
             | Event::PointerOver(ref mut pe)
             | Event::PointerOut(ref mut pe)
             | Event::PointerEnter(ref mut pe)
-            | Event::PointerLeave(ref mut pe) => pe.request_capture,
+            | Event::PointerLeave(ref mut pe)
+            | Event::Wheel(WheelEvent { pointer: ref mut pe, .. })
+            | Event::Pinch(GestureEvent { pointer: ref mut pe, .. })
+            | Event::Rotate(GestureEvent { pointer: ref mut pe, .. })
+            | Event::DragEnter(DragEvent { pointer: ref mut pe, .. })
+            | Event::DragOver(DragEvent { pointer: ref mut pe, .. })
+            | Event::DragLeave(DragEvent { pointer: ref mut pe, .. })
+            | Event::Drop(DragEvent { pointer: ref mut pe, .. })
+            | Event::DragEnd(DragEvent { pointer: ref mut pe, .. })
+            | Event::FileHovered(FileDragEvent { pointer: ref mut pe, .. })
+            | Event::FileDropped(FileDragEvent { pointer: ref mut pe, .. })
+            | Event::FileHoverCancelled(ref mut pe) => pe.request_capture,
             _ => false,
         }
     }
 
+    /// Returns the pointer state of pointer, wheel, gesture, drag-and-drop and file drag events.
+    pub fn pointer_event(&self) -> Option<&PointerEvent> {
+        match self {
+            Event::PointerMove(pe)
+            | Event::PointerUp(pe)
+            | Event::PointerDown(pe)
+            | Event::PointerOver(pe)
+            | Event::PointerOut(pe)
+            | Event::PointerEnter(pe)
+            | Event::PointerLeave(pe)
+            | Event::Wheel(WheelEvent { pointer: pe, .. })
+            | Event::Pinch(GestureEvent { pointer: pe, .. })
+            | Event::Rotate(GestureEvent { pointer: pe, .. })
+            | Event::DragEnter(DragEvent { pointer: pe, .. })
+            | Event::DragOver(DragEvent { pointer: pe, .. })
+            | Event::DragLeave(DragEvent { pointer: pe, .. })
+            | Event::Drop(DragEvent { pointer: pe, .. })
+            | Event::DragEnd(DragEvent { pointer: pe, .. })
+            | Event::FileHovered(FileDragEvent { pointer: pe, .. })
+            | Event::FileDropped(FileDragEvent { pointer: pe, .. })
+            | Event::FileHoverCancelled(pe) => Some(pe),
+            _ => None,
+        }
+    }
+
     pub fn with_offset<R>(&mut self, offset: Vec2, f: impl FnOnce(&mut Event) -> R) -> R {
         self.with_transform(&Affine::translate(offset), f)
     }
@@ -0,0 +1,382 @@
+//! Recording and replay of window input events.
+//!
+//! A recording is the stream of input events (pointer, keyboard, window focus and resize), as translated
+//! by the window, with timestamps relative to the start of the recording. Recordings can be saved to
+//! and loaded from JSON files, and replayed on a window with `Window::replay` or `HeadlessWindow::replay`.
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use keyboard_types::{KeyboardEvent, Modifiers};
+use kurbo::{Point, Size, Vec2};
+use serde::{Deserialize, Serialize};
+
+use crate::application;
+use crate::event::{
+    Event, FileDragEvent, GestureEvent, GesturePhase, ImeEvent, PointerButton, PointerButtons, PointerEvent,
+    PointerType, WheelDelta, WheelEvent,
+};
+
+/// Version of the recording file format.
+const RECORDING_FORMAT_VERSION: u32 = 1;
+
+/// A pointer event, as recorded.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedPointerEvent {
+    /// Position relative to the window.
+    pub position: (f64, f64),
+    pub modifiers: Modifiers,
+    pub buttons: u32,
+    pub button: Option<u16>,
+    pub repeat_count: u8,
+    #[serde(default)]
+    pub pointer_type: PointerType,
+    #[serde(default)]
+    pub pointer_id: u64,
+}
+
+impl RecordedPointerEvent {
+    fn new(pe: &PointerEvent) -> RecordedPointerEvent {
+        RecordedPointerEvent {
+            position: (pe.position.x, pe.position.y),
+            modifiers: pe.modifiers,
+            buttons: pe.buttons.0,
+            button: pe.button.map(|b| b.0),
+            repeat_count: pe.repeat_count,
+            pointer_type: pe.pointer_type,
+            pointer_id: pe.pointer_id,
+        }
+    }
+
+    fn to_pointer_event(&self) -> PointerEvent {
+        PointerEvent {
+            position: Point::new(self.position.0, self.position.1),
+            modifiers: self.modifiers,
+            buttons: PointerButtons(self.buttons),
+            button: self.button.map(PointerButton),
+            repeat_count: self.repeat_count,
+            transform: Default::default(),
+            request_capture: false,
+            pointer_type: self.pointer_type,
+            pointer_id: self.pointer_id,
+        }
+    }
+}
+
+/// Recorded input events.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RecordedEventKind {
+    PointerMove(RecordedPointerEvent),
+    PointerDown(RecordedPointerEvent),
+    PointerUp(RecordedPointerEvent),
+    /// Mouse wheel, with the scroll delta in lines if `lines` is true, in device-independent pixels otherwise.
+    Wheel {
+        pointer: RecordedPointerEvent,
+        delta: (f64, f64),
+        // absent in recordings made before line deltas were recorded, which were all in pixels
+        #[serde(default)]
+        lines: bool,
+    },
+    /// Pinch gesture, with the change in scale factor.
+    Pinch {
+        pointer: RecordedPointerEvent,
+        delta: f64,
+        phase: GesturePhase,
+    },
+    /// Rotation gesture, with the change in angle in degrees.
+    Rotate {
+        pointer: RecordedPointerEvent,
+        delta: f64,
+        phase: GesturePhase,
+    },
+    /// A file dragged from outside the application entered the window.
+    FileHovered {
+        pointer: RecordedPointerEvent,
+        path: PathBuf,
+    },
+    /// A file dragged from outside the application was dropped on the window.
+    FileDropped {
+        pointer: RecordedPointerEvent,
+        path: PathBuf,
+    },
+    FileHoverCancelled(RecordedPointerEvent),
+    KeyDown(KeyboardEvent),
+    KeyUp(KeyboardEvent),
+    Ime(ImeEvent),
+    /// The window gained or lost focus.
+    Focused(bool),
+    /// The window was resized (physical size).
+    Resized { width: u32, height: u32 },
+}
+
+impl RecordedEventKind {
+    /// Converts an event to its recorded form.
+    ///
+    /// Returns `None` for events that aren't input events (e.g. `PointerEnter`, which is derived from pointer moves).
+    pub fn from_event(event: &Event) -> Option<RecordedEventKind> {
+        match event {
+            Event::PointerMove(pe) => Some(RecordedEventKind::PointerMove(RecordedPointerEvent::new(pe))),
+            Event::PointerDown(pe) => Some(RecordedEventKind::PointerDown(RecordedPointerEvent::new(pe))),
+            Event::PointerUp(pe) => Some(RecordedEventKind::PointerUp(RecordedPointerEvent::new(pe))),
+            Event::Wheel(we) => {
+                let (delta, lines) = match we.delta {
+                    WheelDelta::Lines(delta) => (delta, true),
+                    WheelDelta::Pixels(delta) => (delta, false),
+                };
+                Some(RecordedEventKind::Wheel {
+                    pointer: RecordedPointerEvent::new(&we.pointer),
+                    delta: (delta.x, delta.y),
+                    lines,
+                })
+            }
+            Event::Pinch(ge) => Some(RecordedEventKind::Pinch {
+                pointer: RecordedPointerEvent::new(&ge.pointer),
+                delta: ge.delta,
+                phase: ge.phase,
+            }),
+            Event::Rotate(ge) => Some(RecordedEventKind::Rotate {
+                pointer: RecordedPointerEvent::new(&ge.pointer),
+                delta: ge.delta,
+                phase: ge.phase,
+            }),
+            Event::FileHovered(fe) => Some(RecordedEventKind::FileHovered {
+                pointer: RecordedPointerEvent::new(&fe.pointer),
+                path: fe.path.clone(),
+            }),
+            Event::FileDropped(fe) => Some(RecordedEventKind::FileDropped {
+                pointer: RecordedPointerEvent::new(&fe.pointer),
+                path: fe.path.clone(),
+            }),
+            Event::FileHoverCancelled(pe) => Some(RecordedEventKind::FileHoverCancelled(RecordedPointerEvent::new(pe))),
+            Event::KeyDown(ke) => Some(RecordedEventKind::KeyDown(ke.clone())),
+            Event::KeyUp(ke) => Some(RecordedEventKind::KeyUp(ke.clone())),
+            Event::Ime(ime) => Some(RecordedEventKind::Ime(ime.clone())),
+            Event::FocusGained => Some(RecordedEventKind::Focused(true)),
+            Event::FocusLost => Some(RecordedEventKind::Focused(false)),
+            _ => None,
+        }
+    }
+
+    /// Converts back to an event that can be dispatched to a window.
+    ///
+    /// Returns `None` for resize events, which aren't represented by an `Event`.
+    pub fn to_event(&self) -> Option<Event> {
+        match self {
+            RecordedEventKind::PointerMove(pe) => Some(Event::PointerMove(pe.to_pointer_event())),
+            RecordedEventKind::PointerDown(pe) => Some(Event::PointerDown(pe.to_pointer_event())),
+            RecordedEventKind::PointerUp(pe) => Some(Event::PointerUp(pe.to_pointer_event())),
+            RecordedEventKind::Wheel { pointer, delta, lines } => {
+                let delta = Vec2::new(delta.0, delta.1);
+                Some(Event::Wheel(WheelEvent {
+                    pointer: pointer.to_pointer_event(),
+                    delta: if *lines {
+                        WheelDelta::Lines(delta)
+                    } else {
+                        WheelDelta::Pixels(delta)
+                    },
+                }))
+            }
+            RecordedEventKind::Pinch { pointer, delta, phase } => Some(Event::Pinch(GestureEvent {
+                pointer: pointer.to_pointer_event(),
+                delta: *delta,
+                phase: *phase,
+            })),
+            RecordedEventKind::Rotate { pointer, delta, phase } => Some(Event::Rotate(GestureEvent {
+                pointer: pointer.to_pointer_event(),
+                delta: *delta,
+                phase: *phase,
+            })),
+            RecordedEventKind::FileHovered { pointer, path } => Some(Event::FileHovered(FileDragEvent {
+                pointer: pointer.to_pointer_event(),
+                path: path.clone(),
+            })),
+            RecordedEventKind::FileDropped { pointer, path } => Some(Event::FileDropped(FileDragEvent {
+                pointer: pointer.to_pointer_event(),
+                path: path.clone(),
+            })),
+            RecordedEventKind::FileHoverCancelled(pe) => Some(Event::FileHoverCancelled(pe.to_pointer_event())),
+            RecordedEventKind::KeyDown(ke) => Some(Event::KeyDown(ke.clone())),
+            RecordedEventKind::KeyUp(ke) => Some(Event::KeyUp(ke.clone())),
+            RecordedEventKind::Ime(ime) => Some(Event::Ime(ime.clone())),
+            RecordedEventKind::Focused(true) => Some(Event::FocusGained),
+            RecordedEventKind::Focused(false) => Some(Event::FocusLost),
+            RecordedEventKind::Resized { .. } => None,
+        }
+    }
+}
+
+/// A recorded event with its timestamp.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RecordedEvent {
+    /// Time elapsed since the start of the recording.
+    pub time: Duration,
+    pub kind: RecordedEventKind,
+}
+
+/// A recorded stream of window input events.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EventRecording {
+    pub version: u32,
+    /// Physical size of the window when the recording started.
+    pub initial_size: (u32, u32),
+    /// Scale factor of the window when the recording started.
+    pub scale_factor: f64,
+    pub events: Vec<RecordedEvent>,
+}
+
+impl EventRecording {
+    pub fn new(initial_size: Size, scale_factor: f64) -> EventRecording {
+        EventRecording {
+            version: RECORDING_FORMAT_VERSION,
+            initial_size: (initial_size.width as u32, initial_size.height as u32),
+            scale_factor,
+            events: vec![],
+        }
+    }
+
+    /// Loads a recording from a JSON file.
+    pub fn load(path: impl AsRef<Path>) -> Result<EventRecording, anyhow::Error> {
+        let path = path.as_ref();
+        let file = File::open(path).with_context(|| format!("failed to open `{}`", path.display()))?;
+        let recording: EventRecording =
+            serde_json::from_reader(BufReader::new(file)).context("failed to parse event recording")?;
+        if recording.version != RECORDING_FORMAT_VERSION {
+            anyhow::bail!("unsupported event recording version: {}", recording.version);
+        }
+        Ok(recording)
+    }
+
+    /// Saves the recording to a JSON file.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), anyhow::Error> {
+        let path = path.as_ref();
+        let file = File::create(path).with_context(|| format!("failed to create `{}`", path.display()))?;
+        serde_json::to_writer_pretty(BufWriter::new(file), self).context("failed to write event recording")?;
+        Ok(())
+    }
+}
+
+/// Accumulates events into a recording.
+pub(crate) struct EventRecorder {
+    start: Instant,
+    recording: EventRecording,
+}
+
+impl EventRecorder {
+    pub(crate) fn new(initial_size: Size, scale_factor: f64) -> EventRecorder {
+        EventRecorder {
+            start: application::now(),
+            recording: EventRecording::new(initial_size, scale_factor),
+        }
+    }
+
+    pub(crate) fn record(&mut self, kind: RecordedEventKind) {
+        let time = application::now().duration_since(self.start);
+        self.recording.events.push(RecordedEvent { time, kind });
+    }
+
+    pub(crate) fn finish(self) -> EventRecording {
+        self.recording
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use keyboard_types::Key;
+
+    use super::*;
+
+    fn pointer(buttons: PointerButtons, button: Option<PointerButton>) -> RecordedPointerEvent {
+        RecordedPointerEvent {
+            position: (10.0, 10.0),
+            modifiers: Modifiers::empty(),
+            buttons: buttons.0,
+            button: button.map(|b| b.0),
+            repeat_count: 1,
+            pointer_type: PointerType::Mouse,
+            pointer_id: 0,
+        }
+    }
+
+    fn click_recording() -> EventRecording {
+        let mut recording = EventRecording::new(Size::new(200.0, 100.0), 1.0);
+        recording.events = vec![
+            RecordedEvent {
+                time: Duration::ZERO,
+                kind: RecordedEventKind::PointerDown(pointer(
+                    PointerButtons::new().with(PointerButton::LEFT),
+                    Some(PointerButton::LEFT),
+                )),
+            },
+            RecordedEvent {
+                time: Duration::from_millis(100),
+                kind: RecordedEventKind::PointerUp(pointer(PointerButtons::new(), Some(PointerButton::LEFT))),
+            },
+        ];
+        recording
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut recording = click_recording();
+        recording.events.push(RecordedEvent {
+            time: Duration::from_millis(150),
+            kind: RecordedEventKind::Wheel {
+                pointer: pointer(PointerButtons::new(), None),
+                delta: (0.0, 3.0),
+                lines: true,
+            },
+        });
+        recording.events.push(RecordedEvent {
+            time: Duration::from_millis(200),
+            kind: RecordedEventKind::KeyDown(KeyboardEvent {
+                key: Key::Character("a".to_string()),
+                ..Default::default()
+            }),
+        });
+
+        let path = std::env::temp_dir().join(format!("kyute-recording-{}.json", std::process::id()));
+        recording.save(&path).unwrap();
+        let loaded = EventRecording::load(&path);
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(loaded.unwrap(), recording);
+    }
+
+    #[cfg(feature = "headless")]
+    #[test]
+    fn replay_into_headless_window() {
+        use crate::application::TestRuntime;
+        use crate::layout::Sizing;
+        use crate::style::{Style, StyleExt};
+        use crate::widgets::frame::Frame;
+        use crate::window::HeadlessWindow;
+
+        let mut rt = TestRuntime::new();
+        let frame = Frame::new(
+            Style::new()
+                .width(Sizing::Length(100.0.into()))
+                .height(Sizing::Length(50.0.into())),
+        );
+        let window = rt.enter(|| HeadlessWindow::new(&frame, Size::new(200.0, 100.0), 1.0));
+        window.render();
+
+        let clicked = Rc::new(Cell::new(false));
+        rt.spawn({
+            let frame = frame.clone();
+            let clicked = clicked.clone();
+            async move {
+                frame.clicked().await;
+                clicked.set(true);
+            }
+        });
+        rt.run_until_stalled();
+
+        rt.run_until(window.replay(&click_recording()));
+        rt.run_until_stalled();
+        assert!(clicked.get());
+    }
+}
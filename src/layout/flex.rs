@@ -3,7 +3,7 @@ use std::rc::Rc;
 
 use crate::element::{AnyVisual, AttachedProperty, Element, Visual};
 use crate::event::Event;
-use crate::layout::{BoxConstraints, Geometry};
+use crate::layout::{BoxConstraints, Geometry, IntrinsicSizes, Layout};
 use crate::PaintCtx;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Ord, PartialOrd, Default)]
@@ -27,12 +27,44 @@ pub enum CrossAxisAlignment {
     Baseline,
 }
 
+/// Whether flex items wrap onto multiple lines.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Ord, PartialOrd, Default)]
+pub enum FlexWrap {
+    #[default]
+    NoWrap,
+    Wrap,
+}
+
+/// How much of the remaining space along the main axis the item takes (flex-grow in CSS).
 pub struct FlexFactor;
 
 impl AttachedProperty for FlexFactor {
     type Value = f64;
 }
 
+/// How much the item shrinks, relative to the other items, when the items overflow the main axis.
+///
+/// Items don't shrink if unspecified.
+pub struct FlexShrink;
+
+impl AttachedProperty for FlexShrink {
+    type Value = f64;
+}
+
+/// Size of the item along the main axis before growing or shrinking. If unspecified, the size of the content is used.
+pub struct FlexBasis;
+
+impl AttachedProperty for FlexBasis {
+    type Value = f64;
+}
+
+/// Overrides the cross axis alignment of the container for this item.
+pub struct AlignSelf;
+
+impl AttachedProperty for AlignSelf {
+    type Value = CrossAxisAlignment;
+}
+
 /*
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 pub struct Flex {
@@ -197,12 +229,203 @@ fn main_cross_constraints(axis: Axis, min_main: f64, max_main: f64, min_cross: f
     }
 }
 
+/// Flex layout.
+#[derive(Copy, Clone, Debug, PartialEq, Default)]
+pub struct FlexLayout {
+    pub axis: Axis,
+    pub main_axis_alignment: MainAxisAlignment,
+    pub cross_axis_alignment: CrossAxisAlignment,
+    pub wrap: FlexWrap,
+    pub row_gap: f64,
+    pub column_gap: f64,
+}
+
+impl FlexLayout {
+    pub fn row() -> FlexLayout {
+        FlexLayout {
+            axis: Axis::Horizontal,
+            ..Default::default()
+        }
+    }
+
+    pub fn column() -> FlexLayout {
+        FlexLayout {
+            axis: Axis::Vertical,
+            ..Default::default()
+        }
+    }
+}
+
+impl Layout for FlexLayout {
+    fn intrinsic_sizes(&self, children: &[Rc<dyn Visual>]) -> IntrinsicSizes {
+        let mut isizes = IntrinsicSizes::default();
+        for c in children.iter() {
+            let s = c.intrinsic_sizes();
+            match self.axis {
+                Axis::Horizontal => {
+                    // horizontal layout
+                    // width is sum of all children
+                    // height is max of all children
+                    isizes.min.width += s.min.width;
+                    isizes.max.width += s.max.width;
+                    isizes.min.height = isizes.min.height.max(s.min.height);
+                    isizes.max.height = isizes.max.height.max(s.max.height);
+                }
+                Axis::Vertical => {
+                    // vertical layout
+                    // width is max of all children
+                    // height is sum of all children
+                    isizes.min.height += s.min.height;
+                    isizes.max.height += s.max.height;
+                    isizes.min.width = isizes.min.width.max(s.min.width);
+                    isizes.max.width = isizes.max.width.max(s.max.width);
+                }
+            }
+        }
+
+        let gap_count = children.len().saturating_sub(1) as f64;
+        let (main_gap, cross_gap) = match self.axis {
+            Axis::Horizontal => (self.column_gap, self.row_gap),
+            Axis::Vertical => (self.row_gap, self.column_gap),
+        };
+        let main_gaps = main_gap * gap_count;
+        let (min_main, max_main) = match self.axis {
+            Axis::Horizontal => (&mut isizes.min.width, &mut isizes.max.width),
+            Axis::Vertical => (&mut isizes.min.height, &mut isizes.max.height),
+        };
+        *min_main += main_gaps;
+        *max_main += main_gaps;
+
+        if self.wrap == FlexWrap::Wrap {
+            // At the min-content size, each item is on its own line.
+            let mut min_main = 0.0f64;
+            let mut min_cross = cross_gap * gap_count;
+            for c in children.iter() {
+                let s = c.intrinsic_sizes();
+                min_main = min_main.max(s.min.main_length(self.axis));
+                min_cross += s.min.cross_length(self.axis);
+            }
+            isizes.min = Size::from_main_cross(self.axis, min_main, min_cross);
+        }
+        isizes
+    }
+
+    fn intrinsic_height(&self, children: &[Rc<dyn Visual>], width: f64) -> f64 {
+        if children.is_empty() {
+            return 0.0;
+        }
+        let gap_count = (children.len() - 1) as f64;
+        match self.axis {
+            Axis::Vertical => {
+                // children are stacked vertically, each with the full width
+                children.iter().map(|c| c.intrinsic_height(width)).sum::<f64>() + self.row_gap * gap_count
+            }
+            Axis::Horizontal => {
+                let sizes: Vec<IntrinsicSizes> = children.iter().map(|c| c.intrinsic_sizes()).collect();
+                let available = width - self.column_gap * gap_count;
+
+                if self.wrap == FlexWrap::Wrap {
+                    // break into lines of max-content items, like `do_flex_layout`
+                    let mut height = 0.0;
+                    let mut line_height = 0.0f64;
+                    let mut line_width = 0.0;
+                    let mut line_count = 0;
+                    for (child, s) in children.iter().zip(sizes.iter()) {
+                        let item_width = s.max.width.min(width);
+                        if line_width > 0.0 && line_width + self.column_gap + item_width > width {
+                            height += line_height;
+                            line_count += 1;
+                            line_height = 0.0;
+                            line_width = 0.0;
+                        } else if line_width > 0.0 {
+                            line_width += self.column_gap;
+                        }
+                        line_width += item_width;
+                        line_height = line_height.max(child.intrinsic_height(item_width));
+                    }
+                    return height + line_height + self.row_gap * line_count as f64;
+                }
+
+                // Items get a width between their min-content and max-content widths,
+                // proportionally to the available space.
+                let min_total: f64 = sizes.iter().map(|s| s.min.width).sum();
+                let max_total: f64 = sizes.iter().map(|s| s.max.width).sum();
+                let t = if max_total > min_total {
+                    ((available - min_total) / (max_total - min_total)).clamp(0.0, 1.0)
+                } else {
+                    1.0
+                };
+                children
+                    .iter()
+                    .zip(sizes.iter())
+                    .map(|(c, s)| c.intrinsic_height(s.min.width + t * (s.max.width - s.min.width)))
+                    .fold(0.0, f64::max)
+            }
+        }
+    }
+
+    fn layout(&self, children: &[Rc<dyn Visual>], constraints: &BoxConstraints) -> Geometry {
+        do_flex_layout(
+            &FlexLayoutParams {
+                axis: self.axis,
+                constraints: *constraints,
+                cross_axis_alignment: self.cross_axis_alignment,
+                main_axis_alignment: self.main_axis_alignment,
+                wrap: self.wrap,
+                row_gap: self.row_gap,
+                column_gap: self.column_gap,
+            },
+            children,
+        )
+    }
+}
+
 pub struct FlexLayoutParams {
     pub axis: Axis,
     /// When bounded, the flex item will take the maximum size, otherwise it will size to its content.
     pub constraints: BoxConstraints,
     pub cross_axis_alignment: CrossAxisAlignment,
     pub main_axis_alignment: MainAxisAlignment,
+    /// Whether items wrap onto multiple lines when they overflow the main axis.
+    pub wrap: FlexWrap,
+    /// Space between rows: between items of a vertical layout, or between lines of a wrapping horizontal layout.
+    pub row_gap: f64,
+    /// Space between columns: between items of a horizontal layout, or between lines of a wrapping vertical layout.
+    pub column_gap: f64,
+}
+
+/// Per-item state during flex layout.
+struct FlexItem {
+    grow: f64,
+    shrink: f64,
+    basis: Option<f64>,
+    align: CrossAxisAlignment,
+    /// Main axis size before growing or shrinking.
+    hypothetical_main: f64,
+    /// Final main axis size.
+    main: f64,
+    /// Result of the last layout of the item.
+    geometry: Option<Geometry>,
+}
+
+/// Returns the space between items and the offset of the first item for the specified main axis alignment.
+fn main_axis_spacing(alignment: MainAxisAlignment, blank_space: f64, item_count: usize) -> (f64, f64) {
+    let blank_space = blank_space.max(0.0);
+    let space = match alignment {
+        MainAxisAlignment::SpaceBetween if item_count > 1 => blank_space / (item_count - 1) as f64,
+        MainAxisAlignment::SpaceAround => blank_space / item_count as f64,
+        MainAxisAlignment::SpaceEvenly => blank_space / (item_count + 1) as f64,
+        _ => 0.0,
+    };
+    let offset = match alignment {
+        MainAxisAlignment::SpaceBetween => 0.0,
+        MainAxisAlignment::SpaceAround => space / 2.0,
+        MainAxisAlignment::SpaceEvenly => space,
+        MainAxisAlignment::Center => blank_space / 2.0,
+        MainAxisAlignment::Start => 0.0,
+        MainAxisAlignment::End => blank_space,
+    };
+    (space, offset)
 }
 
 // Conforming to CSS:
@@ -210,7 +433,7 @@ pub struct FlexLayoutParams {
 
 pub fn do_flex_layout(p: &FlexLayoutParams, children: &[Rc<dyn Visual>]) -> Geometry {
     let axis = p.axis;
-    let (main_axis_min, main_axis_max, mut cross_axis_min, cross_axis_max) = if axis == Axis::Horizontal {
+    let (main_axis_min, main_axis_max, cross_axis_min, cross_axis_max) = if axis == Axis::Horizontal {
         (
             p.constraints.min.width,
             p.constraints.max.width,
@@ -225,82 +448,133 @@ pub fn do_flex_layout(p: &FlexLayoutParams, children: &[Rc<dyn Visual>]) -> Geom
             p.constraints.max.width,
         )
     };
-
-    // stretch constraints
-    //if p.cross_axis_alignment == CrossAxisAlignment::Stretch {
-    //    cross_axis_min = cross_axis_max;
-    //}
+    let (main_gap, cross_gap) = match axis {
+        Axis::Horizontal => (p.column_gap, p.row_gap),
+        Axis::Vertical => (p.row_gap, p.column_gap),
+    };
+    let wrap = p.wrap == FlexWrap::Wrap && main_axis_max.is_finite();
 
     let child_count = children.len();
+    if child_count == 0 {
+        let size = p.constraints.constrain(Size::ZERO);
+        return Geometry::new(size);
+    }
 
-    let mut flex_factors = Vec::with_capacity(child_count);
+    // Determine the size of each item before growing or shrinking.
+    //
+    // Items that don't grow (zero flex factor) get their natural size along the main axis.
+    // Unless lines can wrap, items that grow start from zero, and take their share of the remaining space.
+    let mut items = Vec::with_capacity(child_count);
     for child in children.iter() {
-        if let Some(flex) = FlexFactor.get(&**child) {
-            flex_factors.push(flex);
+        let grow = FlexFactor.get(&**child).unwrap_or(0.0);
+        let shrink = FlexShrink.get(&**child).unwrap_or(0.0);
+        let basis = FlexBasis.get(&**child);
+        let align = AlignSelf.get(&**child).unwrap_or(p.cross_axis_alignment);
+        let mut geometry = None;
+        let hypothetical_main = if let Some(basis) = basis {
+            basis
+        } else if grow != 0.0 && !wrap {
+            0.0
         } else {
-            flex_factors.push(0.0);
-        }
-    }
-
-    let flex_sum: f64 = flex_factors.iter().sum(); // sum of flex factors
-
-    let mut non_flex_main_total = 0.0; // total size of inflexible children
-    let mut child_geoms = vec![Geometry::ZERO; child_count];
-    let mut child_offsets = vec![Vec2::ZERO; child_count];
-
-    // Layout each child with a zero flex factor (i.e. they don't expand along the main axis, they get their natural size instead)
-    for (i, child) in children.iter().enumerate() {
-        if flex_factors[i] == 0.0 {
             // layout child with unbounded main axis constraints and the incoming cross axis constraints
             let child_constraints = main_cross_constraints(axis, 0.0, f64::INFINITY, 0.0, cross_axis_max);
-            child_geoms[i] = child.do_layout(&child_constraints);
-            non_flex_main_total += child_geoms[i].size.main_length(axis);
+            let g = child.do_layout(&child_constraints);
+            geometry = Some(g);
+            g.size.main_length(axis)
+        };
+        items.push(FlexItem {
+            grow,
+            shrink,
+            basis,
+            align,
+            hypothetical_main,
+            main: hypothetical_main,
+            geometry,
+        });
+    }
+
+    // Break the items into lines.
+    let mut lines: Vec<std::ops::Range<usize>> = vec![];
+    let mut line_start = 0;
+    let mut line_length = 0.0;
+    for (i, item) in items.iter().enumerate() {
+        let gap = if i > line_start { main_gap } else { 0.0 };
+        if wrap && i > line_start && line_length + gap + item.hypothetical_main > main_axis_max {
+            lines.push(line_start..i);
+            line_start = i;
+            line_length = item.hypothetical_main;
+        } else {
+            line_length += gap + item.hypothetical_main;
         }
     }
+    lines.push(line_start..child_count);
+
+    // Resolve the main axis size of the items of each line, and lay them out.
+    for line in lines.iter() {
+        let line_items = &mut items[line.clone()];
+        let gaps = main_gap * (line_items.len() - 1) as f64;
+        let hypothetical_total: f64 = line_items.iter().map(|item| item.hypothetical_main).sum();
+        let free_space = main_axis_max - hypothetical_total - gaps;
+        let grow_sum: f64 = line_items.iter().map(|item| item.grow).sum();
+        let scaled_shrink_sum: f64 = line_items.iter().map(|item| item.shrink * item.hypothetical_main).sum();
+
+        for item in line_items.iter_mut() {
+            item.main = if free_space > 0.0 && grow_sum > 0.0 {
+                item.hypothetical_main + free_space * item.grow / grow_sum
+            } else if free_space < 0.0 && free_space.is_finite() && scaled_shrink_sum > 0.0 {
+                let shrink = item.shrink * item.hypothetical_main / scaled_shrink_sum;
+                (item.hypothetical_main + free_space * shrink).max(0.0)
+            } else {
+                item.hypothetical_main
+            };
+        }
 
-    // Divide the remaining main axis space among the children with non-zero flex factors
-    let remaining_main = main_axis_max - non_flex_main_total;
-    for (i, child) in children.iter().enumerate() {
-        if flex_factors[i] != 0.0 {
-            let main_size = remaining_main * flex_factors[i] / flex_sum;
-            // pass loose constraints along the main axis; it's the child's job to decide whether to fill the space or not
-            let child_constraints = main_cross_constraints(axis, 0.0, main_size, 0.0, cross_axis_max);
-            child_geoms[i] = child.do_layout(&child_constraints);
+        for (item, child) in line_items.iter_mut().zip(&children[line.clone()]) {
+            let measured = item.geometry.map(|g| g.size.main_length(axis));
+            if measured == Some(item.main) {
+                continue;
+            }
+            // pass loose constraints along the main axis; it's the child's job to decide whether to fill
+            // the space or not. Items with an explicit basis are sized exactly.
+            let min_main = if item.basis.is_some() { item.main } else { 0.0 };
+            let child_constraints = main_cross_constraints(axis, min_main, item.main, 0.0, cross_axis_max);
+            item.geometry = Some(child.do_layout(&child_constraints));
         }
     }
 
+    let geometry = |item: &FlexItem| item.geometry.unwrap_or(Geometry::ZERO);
+
     // Determine the main-axis extent.
-    let main_axis_content_size: f64 = child_geoms.iter().map(|g| g.size.main_length(axis)).sum();
+    let line_main_lengths: Vec<f64> = lines
+        .iter()
+        .map(|line| {
+            let gaps = main_gap * (line.len() - 1) as f64;
+            items[line.clone()]
+                .iter()
+                .map(|item| geometry(item).size.main_length(axis))
+                .sum::<f64>()
+                + gaps
+        })
+        .collect();
+    let main_axis_content_size = line_main_lengths.iter().copied().fold(0.0, f64::max);
     let main_axis_size = main_axis_content_size.max(main_axis_min).min(main_axis_max);
-    let blank_space = main_axis_size - main_axis_content_size;
-
-    // Position the children, depending on main axis alignment
-    let space = match p.main_axis_alignment {
-        MainAxisAlignment::SpaceBetween => blank_space / (child_count - 1) as f64,
-        MainAxisAlignment::SpaceAround => blank_space / child_count as f64,
-        MainAxisAlignment::SpaceEvenly => blank_space / (child_count + 1) as f64,
-        MainAxisAlignment::Center | MainAxisAlignment::Start | MainAxisAlignment::End => 0.0,
-    };
-    let mut offset = match p.main_axis_alignment {
-        MainAxisAlignment::SpaceBetween => 0.0,
-        MainAxisAlignment::SpaceAround => space / 2.0,
-        MainAxisAlignment::SpaceEvenly => space,
-        MainAxisAlignment::Center => blank_space / 2.0,
-        MainAxisAlignment::Start => 0.0,
-        MainAxisAlignment::End => blank_space,
-    };
-
-    for (i, _) in children.iter().enumerate() {
-        child_offsets[i].set_main_axis_offset(axis, offset);
-        offset += child_geoms[i].size.main_length(axis) + space;
-    }
 
-    let cross_axis_content_size = child_geoms
+    // Determine the cross-axis extent of each line, and of the container.
+    let mut line_cross_lengths: Vec<f64> = lines
         .iter()
-        .map(|g| g.size.cross_length(axis))
-        .reduce(f64::max)
-        .unwrap();
+        .map(|line| {
+            items[line.clone()]
+                .iter()
+                .map(|item| geometry(item).size.cross_length(axis))
+                .fold(0.0, f64::max)
+        })
+        .collect();
+    let cross_axis_content_size = line_cross_lengths.iter().sum::<f64>() + cross_gap * (lines.len() - 1) as f64;
     let cross_axis_size = cross_axis_content_size.clamp(cross_axis_min, cross_axis_max);
+    if lines.len() == 1 {
+        // a single line fills the container
+        line_cross_lengths[0] = cross_axis_size;
+    }
 
     /*let mut max_baseline: f64 = 0.0;
     for c in child_geoms.iter() {
@@ -322,23 +596,47 @@ pub fn do_flex_layout(p: &FlexLayoutParams, children: &[Rc<dyn Visual>]) -> Geom
         _ => max_cross_axis_size,
     };*/
 
-
-    // Position the children on the cross axis
-    for (i, c) in children.iter().enumerate() {
-        let size = child_geoms[i].size.cross_length(axis);
-        let offset = match p.cross_axis_alignment {
-            CrossAxisAlignment::Start => 0.0,
-            CrossAxisAlignment::End => cross_axis_size - size,
-            CrossAxisAlignment::Center => (cross_axis_size - size) / 2.0,
-            CrossAxisAlignment::Stretch => 0.0,
-            CrossAxisAlignment::Baseline => {
-                0.0 // TODO
-                /*let baseline = child_geoms[i].baseline.unwrap_or(size);
-                max_baseline - baseline*/
+    // Position the children
+    let mut line_cross_offset = 0.0;
+    for (line_index, line) in lines.iter().enumerate() {
+        let line_cross_size = line_cross_lengths[line_index];
+        let blank_space = main_axis_size - line_main_lengths[line_index];
+        let (space, mut offset) = main_axis_spacing(p.main_axis_alignment, blank_space, line.len());
+
+        for (item, child) in items[line.clone()].iter_mut().zip(&children[line.clone()]) {
+            // stretched items are laid out again with the cross size of the line
+            if item.align == CrossAxisAlignment::Stretch
+                && geometry(item).size.cross_length(axis) < line_cross_size
+                && line_cross_size.is_finite()
+            {
+                let min_main = if item.basis.is_some() { item.main } else { 0.0 };
+                let child_constraints =
+                    main_cross_constraints(axis, min_main, item.main, line_cross_size, line_cross_size);
+                item.geometry = Some(child.do_layout(&child_constraints));
             }
-        };
-        child_offsets[i].set_cross_axis_offset(axis, offset);
-        c.set_offset(child_offsets[i]);
+
+            let g = geometry(item);
+            let size = g.size.cross_length(axis);
+            let cross_offset = match item.align {
+                CrossAxisAlignment::Start => 0.0,
+                CrossAxisAlignment::End => line_cross_size - size,
+                CrossAxisAlignment::Center => (line_cross_size - size) / 2.0,
+                CrossAxisAlignment::Stretch => 0.0,
+                CrossAxisAlignment::Baseline => {
+                    0.0 // TODO
+                    /*let baseline = child_geoms[i].baseline.unwrap_or(size);
+                    max_baseline - baseline*/
+                }
+            };
+
+            let mut child_offset = Vec2::ZERO;
+            child_offset.set_main_axis_offset(axis, offset);
+            child_offset.set_cross_axis_offset(axis, line_cross_offset + cross_offset);
+            child.set_offset(child_offset);
+            offset += g.size.main_length(axis) + main_gap + space;
+        }
+
+        line_cross_offset += line_cross_size + cross_gap;
     }
 
     let size = Size::from_main_cross(axis, main_axis_size, cross_axis_size);
@@ -349,3 +647,77 @@ pub fn do_flex_layout(p: &FlexLayoutParams, children: &[Rc<dyn Visual>]) -> Geom
         paint_bounding_rect: Rect::from_origin_size(Point::ORIGIN, size),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A visual with a fixed preferred size.
+    struct Fixed {
+        element: Element,
+        size: Size,
+    }
+
+    impl Visual for Fixed {
+        fn element(&self) -> &Element {
+            &self.element
+        }
+
+        fn layout(&self, _children: &[Rc<dyn Visual>], constraints: &BoxConstraints) -> Geometry {
+            Geometry::new(constraints.constrain(self.size))
+        }
+    }
+
+    fn fixed(width: f64, height: f64) -> Rc<dyn Visual> {
+        Element::new_derived(|element| Fixed {
+            element,
+            size: Size::new(width, height),
+        })
+    }
+
+    fn row(width: f64, wrap: FlexWrap, column_gap: f64, row_gap: f64) -> FlexLayoutParams {
+        FlexLayoutParams {
+            axis: Axis::Horizontal,
+            constraints: BoxConstraints::loose(Size::new(width, 100.0)),
+            cross_axis_alignment: CrossAxisAlignment::Start,
+            main_axis_alignment: MainAxisAlignment::Start,
+            wrap,
+            row_gap,
+            column_gap,
+        }
+    }
+
+    fn offsets(children: &[Rc<dyn Visual>]) -> Vec<Vec2> {
+        children.iter().map(|child| child.transform().translation()).collect()
+    }
+
+    #[test]
+    fn wrap_with_gaps() {
+        let children = vec![fixed(40.0, 20.0), fixed(40.0, 20.0), fixed(40.0, 20.0)];
+        let geometry = do_flex_layout(&row(100.0, FlexWrap::Wrap, 10.0, 5.0), &children);
+
+        // the third item doesn't fit on the first line (40 + 10 + 40 + 10 + 40 > 100)
+        assert_eq!(
+            offsets(&children),
+            vec![Vec2::new(0.0, 0.0), Vec2::new(50.0, 0.0), Vec2::new(0.0, 25.0)]
+        );
+        assert_eq!(geometry.size, Size::new(90.0, 45.0));
+    }
+
+    #[test]
+    fn shrink() {
+        let children = vec![fixed(80.0, 20.0), fixed(40.0, 20.0), fixed(30.0, 20.0)];
+        FlexShrink.set(&*children[0], 1.0);
+        FlexShrink.set(&*children[1], 1.0);
+        let geometry = do_flex_layout(&row(120.0, FlexWrap::NoWrap, 0.0, 0.0), &children);
+
+        // the 30px overflow is taken from the shrinking items in proportion to their size
+        let widths: Vec<f64> = children.iter().map(|child| child.geometry().size.width).collect();
+        assert_eq!(widths, vec![60.0, 30.0, 30.0]);
+        assert_eq!(
+            offsets(&children),
+            vec![Vec2::new(0.0, 0.0), Vec2::new(60.0, 0.0), Vec2::new(90.0, 0.0)]
+        );
+        assert_eq!(geometry.size, Size::new(120.0, 20.0));
+    }
+}
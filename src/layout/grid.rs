@@ -0,0 +1,537 @@
+//! Grid layout.
+//!
+//! Modeled after (a subset of) CSS grid: children are placed in cells of a grid made of row and column tracks.
+//! Track sizes are either fixed, a fraction of the remaining space (`fr` units), or determined by the content.
+//! Children are placed with the `GridRow`/`GridColumn` attached properties (and can span multiple cells with
+//! `GridRowSpan`/`GridColumnSpan`); children without an explicit position are placed in the next free cell,
+//! row by row.
+use std::rc::Rc;
+
+use kurbo::{Point, Rect, Size, Vec2};
+
+use crate::element::{AttachedProperty, Visual};
+use crate::layout::{BoxConstraints, Geometry, IntrinsicSizes, Layout};
+
+/// Size of a grid track (row or column).
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum GridTrack {
+    /// Fixed length.
+    Fixed(f64),
+    /// Fraction of the space left after sizing the other tracks (`fr` unit in CSS).
+    Fraction(f64),
+    /// Min-content size of the items in the track.
+    MinContent,
+    /// Max-content size of the items in the track.
+    #[default]
+    MaxContent,
+}
+
+/// Row of the grid item (zero-based).
+pub struct GridRow;
+
+impl AttachedProperty for GridRow {
+    type Value = usize;
+}
+
+/// Column of the grid item (zero-based).
+pub struct GridColumn;
+
+impl AttachedProperty for GridColumn {
+    type Value = usize;
+}
+
+/// Number of rows spanned by the grid item (1 if unspecified).
+pub struct GridRowSpan;
+
+impl AttachedProperty for GridRowSpan {
+    type Value = usize;
+}
+
+/// Number of columns spanned by the grid item (1 if unspecified).
+pub struct GridColumnSpan;
+
+impl AttachedProperty for GridColumnSpan {
+    type Value = usize;
+}
+
+/// Grid layout.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Grid {
+    /// Explicit row tracks.
+    pub rows: Vec<GridTrack>,
+    /// Explicit column tracks.
+    pub columns: Vec<GridTrack>,
+    /// Size of rows created implicitly to hold items placed outside the explicit rows.
+    pub auto_rows: GridTrack,
+    /// Size of columns created implicitly to hold items placed outside the explicit columns.
+    pub auto_columns: GridTrack,
+    /// Space between rows.
+    pub row_gap: f64,
+    /// Space between columns.
+    pub column_gap: f64,
+}
+
+impl Grid {
+    pub fn new(rows: Vec<GridTrack>, columns: Vec<GridTrack>) -> Grid {
+        Grid {
+            rows,
+            columns,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_gaps(mut self, row_gap: f64, column_gap: f64) -> Grid {
+        self.row_gap = row_gap;
+        self.column_gap = column_gap;
+        self
+    }
+
+    /// Returns the tracks of the grid, extended with implicit tracks up to the specified count.
+    fn tracks(explicit: &[GridTrack], auto: GridTrack, count: usize) -> Vec<GridTrack> {
+        let mut tracks = explicit.to_vec();
+        if tracks.len() < count {
+            tracks.resize(count, auto);
+        }
+        tracks
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Placement
+
+/// Position of an item in the grid.
+#[derive(Copy, Clone, Debug)]
+struct Placement {
+    row: usize,
+    column: usize,
+    row_span: usize,
+    column_span: usize,
+}
+
+/// Tracks the occupied cells of the grid during placement.
+struct Occupancy {
+    columns: usize,
+    cells: Vec<Vec<bool>>,
+}
+
+impl Occupancy {
+    fn is_free(&self, row: usize, column: usize, row_span: usize, column_span: usize) -> bool {
+        (row..row + row_span).all(|r| {
+            let Some(cells) = self.cells.get(r) else { return true };
+            (column..column + column_span).all(|c| !cells.get(c).copied().unwrap_or(false))
+        })
+    }
+
+    fn occupy(&mut self, p: &Placement) {
+        for r in p.row..p.row + p.row_span {
+            if self.cells.len() <= r {
+                self.cells.resize(r + 1, vec![]);
+            }
+            let row = &mut self.cells[r];
+            if row.len() < p.column + p.column_span {
+                row.resize(p.column + p.column_span, false);
+            }
+            for c in p.column..p.column + p.column_span {
+                row[c] = true;
+            }
+        }
+    }
+}
+
+/// Determines the cells of each child.
+fn place_items(columns: usize, children: &[Rc<dyn Visual>]) -> Vec<Placement> {
+    let columns = columns.max(1);
+    let mut occupancy = Occupancy { columns, cells: vec![] };
+    let mut placements = Vec::with_capacity(children.len());
+    // auto-placement cursor
+    let (mut cursor_row, mut cursor_column) = (0, 0);
+
+    for child in children.iter() {
+        let row = GridRow.get(&**child);
+        let column = GridColumn.get(&**child);
+        let row_span = GridRowSpan.get(&**child).unwrap_or(1).max(1);
+        let column_span = GridColumnSpan.get(&**child).unwrap_or(1).max(1);
+
+        let placement = match (row, column) {
+            (Some(row), Some(column)) => Placement {
+                row,
+                column,
+                row_span,
+                column_span,
+            },
+            (Some(row), None) => {
+                // first free column in the row
+                let mut column = 0;
+                while !occupancy.is_free(row, column, row_span, column_span) {
+                    column += 1;
+                }
+                Placement {
+                    row,
+                    column,
+                    row_span,
+                    column_span,
+                }
+            }
+            (None, Some(column)) => {
+                // first free row in the column
+                let mut row = 0;
+                while !occupancy.is_free(row, column, row_span, column_span) {
+                    row += 1;
+                }
+                Placement {
+                    row,
+                    column,
+                    row_span,
+                    column_span,
+                }
+            }
+            (None, None) => {
+                // next free cell after the cursor, row by row
+                let column_span = column_span.min(occupancy.columns);
+                loop {
+                    if cursor_column + column_span > occupancy.columns {
+                        cursor_row += 1;
+                        cursor_column = 0;
+                    }
+                    if occupancy.is_free(cursor_row, cursor_column, row_span, column_span) {
+                        break;
+                    }
+                    cursor_column += 1;
+                }
+                let placement = Placement {
+                    row: cursor_row,
+                    column: cursor_column,
+                    row_span,
+                    column_span,
+                };
+                cursor_column += column_span;
+                placement
+            }
+        };
+
+        occupancy.occupy(&placement);
+        placements.push(placement);
+    }
+
+    placements
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Track sizing
+
+/// Contribution of an item to the size of the tracks it spans.
+#[derive(Copy, Clone, Debug)]
+struct Contribution {
+    start: usize,
+    span: usize,
+    min: f64,
+    max: f64,
+}
+
+/// Computes the size of each track along one axis.
+///
+/// # Arguments
+///
+/// * tracks the track sizing functions
+/// * gap space between tracks
+/// * available space available for the tracks and gaps (can be infinite)
+/// * contributions sizes of the items in the tracks
+fn size_tracks(tracks: &[GridTrack], gap: f64, available: f64, contributions: &[Contribution]) -> Vec<f64> {
+    let n = tracks.len();
+    let mut sizes: Vec<f64> = tracks
+        .iter()
+        .map(|t| match *t {
+            GridTrack::Fixed(len) => len,
+            _ => 0.0,
+        })
+        .collect();
+    // min-content and max-content contributions of the items in flexible tracks
+    let mut fr_min = vec![0.0f64; n];
+    let mut fr_max = vec![0.0f64; n];
+
+    // items spanning a single track
+    for c in contributions.iter().filter(|c| c.span == 1) {
+        match tracks[c.start] {
+            GridTrack::Fixed(_) => {}
+            GridTrack::MinContent => sizes[c.start] = sizes[c.start].max(c.min),
+            GridTrack::MaxContent => sizes[c.start] = sizes[c.start].max(c.max),
+            GridTrack::Fraction(_) => {
+                fr_min[c.start] = fr_min[c.start].max(c.min);
+                fr_max[c.start] = fr_max[c.start].max(c.max);
+            }
+        }
+    }
+
+    // items spanning multiple tracks: distribute the missing space evenly among the content-sized tracks they span
+    for c in contributions.iter().filter(|c| c.span > 1) {
+        let span = c.start..c.start + c.span;
+        let current: f64 = sizes[span.clone()].iter().sum::<f64>() + gap * (c.span - 1) as f64;
+        let content_tracks: Vec<usize> = span
+            .filter(|&i| matches!(tracks[i], GridTrack::MinContent | GridTrack::MaxContent))
+            .collect();
+        if current < c.max && !content_tracks.is_empty() {
+            let extra = (c.max - current) / content_tracks.len() as f64;
+            for i in content_tracks {
+                sizes[i] += extra;
+            }
+        }
+    }
+
+    // flexible tracks
+    let fr_sum: f64 = tracks
+        .iter()
+        .map(|t| match *t {
+            GridTrack::Fraction(fr) => fr,
+            _ => 0.0,
+        })
+        .sum();
+    if fr_sum > 0.0 {
+        let fr_size = if available.is_finite() {
+            let used: f64 = sizes.iter().sum::<f64>() + gap * n.saturating_sub(1) as f64;
+            (available - used).max(0.0) / fr_sum
+        } else {
+            // indefinite space: size flexible tracks so that their content fits, respecting the ratios
+            tracks
+                .iter()
+                .enumerate()
+                .filter_map(|(i, t)| match *t {
+                    GridTrack::Fraction(fr) if fr > 0.0 => Some(fr_max[i] / fr),
+                    _ => None,
+                })
+                .fold(0.0, f64::max)
+        };
+        for (i, t) in tracks.iter().enumerate() {
+            if let GridTrack::Fraction(fr) = *t {
+                sizes[i] = (fr * fr_size).max(fr_min[i]);
+            }
+        }
+    }
+
+    sizes
+}
+
+/// Returns the offsets of each track from the sizes of the tracks.
+fn track_offsets(sizes: &[f64], gap: f64) -> Vec<f64> {
+    let mut offsets = Vec::with_capacity(sizes.len());
+    let mut offset = 0.0;
+    for size in sizes {
+        offsets.push(offset);
+        offset += size + gap;
+    }
+    offsets
+}
+
+/// Total length of tracks and gaps.
+fn total_length(sizes: &[f64], gap: f64) -> f64 {
+    sizes.iter().sum::<f64>() + gap * sizes.len().saturating_sub(1) as f64
+}
+
+/// Length of the tracks spanned by an item, including gaps.
+fn span_length(sizes: &[f64], gap: f64, start: usize, span: usize) -> f64 {
+    total_length(&sizes[start..start + span], gap)
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+impl Grid {
+    /// Places the children and returns the row and column tracks (including implicit tracks).
+    fn resolve(&self, children: &[Rc<dyn Visual>]) -> (Vec<Placement>, Vec<GridTrack>, Vec<GridTrack>) {
+        let placements = place_items(self.columns.len(), children);
+        let row_count = placements.iter().map(|p| p.row + p.row_span).max().unwrap_or(0);
+        let column_count = placements.iter().map(|p| p.column + p.column_span).max().unwrap_or(0);
+        let rows = Grid::tracks(&self.rows, self.auto_rows, row_count);
+        let columns = Grid::tracks(&self.columns, self.auto_columns, column_count);
+        (placements, rows, columns)
+    }
+
+    /// Sizes the columns from the intrinsic widths of the items.
+    fn column_sizes(
+        &self,
+        children: &[Rc<dyn Visual>],
+        placements: &[Placement],
+        columns: &[GridTrack],
+        available_width: f64,
+    ) -> Vec<f64> {
+        let column_contributions: Vec<_> = children
+            .iter()
+            .zip(placements.iter())
+            .map(|(child, p)| {
+                let s = child.intrinsic_sizes();
+                Contribution {
+                    start: p.column,
+                    span: p.column_span,
+                    min: s.min.width,
+                    max: s.max.width,
+                }
+            })
+            .collect();
+        size_tracks(columns, self.column_gap, available_width, &column_contributions)
+    }
+}
+
+impl Layout for Grid {
+    fn intrinsic_sizes(&self, children: &[Rc<dyn Visual>]) -> IntrinsicSizes {
+        let (placements, rows, columns) = self.resolve(children);
+        let mut column_contributions = Vec::with_capacity(children.len());
+        let mut row_contributions = Vec::with_capacity(children.len());
+        for (child, p) in children.iter().zip(placements.iter()) {
+            let s = child.intrinsic_sizes();
+            column_contributions.push(Contribution {
+                start: p.column,
+                span: p.column_span,
+                min: s.min.width,
+                max: s.max.width,
+            });
+            row_contributions.push(Contribution {
+                start: p.row,
+                span: p.row_span,
+                min: s.min.height,
+                max: s.max.height,
+            });
+        }
+
+        // Min-content sizes: flexible tracks shrink to the min-content size of their items.
+        let min_contributions = |contributions: &[Contribution]| -> Vec<Contribution> {
+            contributions.iter().map(|c| Contribution { max: c.min, ..*c }).collect()
+        };
+        let min_width = total_length(
+            &size_tracks(
+                &columns,
+                self.column_gap,
+                f64::INFINITY,
+                &min_contributions(&column_contributions),
+            ),
+            self.column_gap,
+        );
+        let min_height = total_length(
+            &size_tracks(&rows, self.row_gap, f64::INFINITY, &min_contributions(&row_contributions)),
+            self.row_gap,
+        );
+        let max_width = total_length(
+            &size_tracks(&columns, self.column_gap, f64::INFINITY, &column_contributions),
+            self.column_gap,
+        );
+        let max_height = total_length(
+            &size_tracks(&rows, self.row_gap, f64::INFINITY, &row_contributions),
+            self.row_gap,
+        );
+
+        IntrinsicSizes {
+            min: Size::new(min_width, min_height),
+            max: Size::new(max_width, max_height),
+        }
+    }
+
+    fn intrinsic_height(&self, children: &[Rc<dyn Visual>], width: f64) -> f64 {
+        let (placements, rows, columns) = self.resolve(children);
+        let column_sizes = self.column_sizes(children, &placements, &columns, width);
+        let row_contributions: Vec<_> = children
+            .iter()
+            .zip(placements.iter())
+            .map(|(child, p)| {
+                let cell_width = span_length(&column_sizes, self.column_gap, p.column, p.column_span);
+                let height = child.intrinsic_height(cell_width);
+                Contribution {
+                    start: p.row,
+                    span: p.row_span,
+                    min: height,
+                    max: height,
+                }
+            })
+            .collect();
+        total_length(
+            &size_tracks(&rows, self.row_gap, f64::INFINITY, &row_contributions),
+            self.row_gap,
+        )
+    }
+
+    fn layout(&self, children: &[Rc<dyn Visual>], constraints: &BoxConstraints) -> Geometry {
+        let (placements, rows, columns) = self.resolve(children);
+
+        let column_sizes = self.column_sizes(children, &placements, &columns, constraints.max.width);
+        let column_offsets = track_offsets(&column_sizes, self.column_gap);
+
+        // Measure the items with the width of their cells, then size the rows from the measured heights.
+        let cell_widths: Vec<f64> = placements
+            .iter()
+            .map(|p| span_length(&column_sizes, self.column_gap, p.column, p.column_span))
+            .collect();
+        let mut child_geoms: Vec<Geometry> = children
+            .iter()
+            .zip(cell_widths.iter())
+            .map(|(child, &width)| child.do_layout(&BoxConstraints::loose(Size::new(width, f64::INFINITY))))
+            .collect();
+        let row_contributions: Vec<_> = placements
+            .iter()
+            .zip(child_geoms.iter())
+            .map(|(p, g)| Contribution {
+                start: p.row,
+                span: p.row_span,
+                min: g.size.height,
+                max: g.size.height,
+            })
+            .collect();
+        let row_sizes = size_tracks(&rows, self.row_gap, constraints.max.height, &row_contributions);
+        let row_offsets = track_offsets(&row_sizes, self.row_gap);
+
+        // Lay out the items again if their cell is smaller than their measured height (fixed or flexible rows).
+        for (i, child) in children.iter().enumerate() {
+            let p = &placements[i];
+            let cell_height = span_length(&row_sizes, self.row_gap, p.row, p.row_span);
+            if child_geoms[i].size.height > cell_height {
+                child_geoms[i] = child.do_layout(&BoxConstraints::loose(Size::new(cell_widths[i], cell_height)));
+            }
+            child.set_offset(Vec2::new(column_offsets[p.column], row_offsets[p.row]));
+        }
+
+        let size = constraints.constrain(Size::new(
+            total_length(&column_sizes, self.column_gap),
+            total_length(&row_sizes, self.row_gap),
+        ));
+        // baseline of the first item in the first row
+        let baseline = placements
+            .iter()
+            .zip(child_geoms.iter())
+            .find(|(p, _)| p.row == 0)
+            .and_then(|(_, g)| g.baseline);
+
+        Geometry {
+            size,
+            baseline,
+            bounding_rect: Rect::from_origin_size(Point::ORIGIN, size),
+            paint_bounding_rect: Rect::from_origin_size(Point::ORIGIN, size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn track_sizing() {
+        let tracks = [
+            GridTrack::Fixed(100.0),
+            GridTrack::MaxContent,
+            GridTrack::Fraction(1.0),
+            GridTrack::Fraction(3.0),
+        ];
+        let contributions = [
+            Contribution {
+                start: 1,
+                span: 1,
+                min: 10.0,
+                max: 50.0,
+            },
+            // spans the fixed and max-content tracks: grows the max-content track
+            Contribution {
+                start: 0,
+                span: 2,
+                min: 0.0,
+                max: 170.0,
+            },
+        ];
+        let sizes = size_tracks(&tracks, 10.0, 500.0, &contributions);
+        assert_eq!(sizes, vec![100.0, 60.0, 77.5, 232.5]);
+    }
+}
@@ -1,12 +1,17 @@
 //! Types and functions used for layouting widgets.
 use kurbo::{Insets, Rect, Size, Vec2};
+use std::rc::Rc;
 use std::{
     fmt,
     hash::{Hash, Hasher},
     ops::{Range, RangeBounds},
 };
 
+use crate::element::Visual;
+
 pub mod flex;
+pub mod grid;
+pub mod stack;
 
 #[derive(Copy, Clone, PartialEq)]
 #[cfg_attr(feature = "serializing", derive(serde::Deserialize))]
@@ -156,6 +161,10 @@ impl BoxConstraints {
         }
     }
 
+    pub fn tight(size: Size) -> BoxConstraints {
+        BoxConstraints { min: size, max: size }
+    }
+
     pub fn loose(size: Size) -> BoxConstraints {
         BoxConstraints {
             min: Size::ZERO,
@@ -278,6 +287,31 @@ pub enum Sizing {
     MaxContent,
 }
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Layout
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// A layout algorithm for the children of a container.
+///
+/// Containers like `Frame` handle their own sizing, padding and decorations, and delegate
+/// the placement of their children to a `Layout`.
+pub trait Layout {
+    /// Returns the intrinsic sizes of the content made of the specified children.
+    ///
+    /// See `Visual::intrinsic_sizes`.
+    fn intrinsic_sizes(&self, children: &[Rc<dyn Visual>]) -> IntrinsicSizes;
+
+    /// Returns the height of the content when laid out with the specified width.
+    fn intrinsic_height(&self, children: &[Rc<dyn Visual>], width: f64) -> f64;
+
+    /// Lays out the children within the specified constraints.
+    ///
+    /// This should call `do_layout` on each child and set their offsets (relative to the content box).
+    ///
+    /// Returns the geometry of the content box.
+    fn layout(&self, children: &[Rc<dyn Visual>], constraints: &BoxConstraints) -> Geometry;
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 // Geometry
 ////////////////////////////////////////////////////////////////////////////////////////////////////
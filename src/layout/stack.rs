@@ -0,0 +1,185 @@
+//! Stack layout.
+//!
+//! Children are stacked on top of each other. Children with insets (`Top`, `Left`, `Right`, `Bottom` attached
+//! properties) are positioned relative to the edges of the container, like `position: absolute` in CSS, and don't
+//! contribute to the size of the container. They can overflow the container, in which case they are included
+//! in its bounding rectangle. Use the `ZIndex` attached property to control the order in which they are painted.
+use std::rc::Rc;
+
+use kurbo::{Size, Vec2};
+
+use crate::element::{AttachedProperty, Visual};
+use crate::layout::{Alignment, BoxConstraints, Geometry, IntrinsicSizes, Layout, LengthOrPercentage};
+
+/// Distance between the top edge of the item and the top edge of the container.
+pub struct Top;
+
+impl AttachedProperty for Top {
+    type Value = LengthOrPercentage;
+}
+
+/// Distance between the left edge of the item and the left edge of the container.
+pub struct Left;
+
+impl AttachedProperty for Left {
+    type Value = LengthOrPercentage;
+}
+
+/// Distance between the right edge of the item and the right edge of the container.
+pub struct Right;
+
+impl AttachedProperty for Right {
+    type Value = LengthOrPercentage;
+}
+
+/// Distance between the bottom edge of the item and the bottom edge of the container.
+pub struct Bottom;
+
+impl AttachedProperty for Bottom {
+    type Value = LengthOrPercentage;
+}
+
+/// Insets of a positioned item, resolved against the size of the container.
+struct Insets {
+    top: Option<f64>,
+    left: Option<f64>,
+    right: Option<f64>,
+    bottom: Option<f64>,
+}
+
+impl Insets {
+    fn get(child: &dyn Visual, container: Size) -> Option<Insets> {
+        let insets = Insets {
+            top: Top.get(child).map(|v| v.resolve(container.height)),
+            left: Left.get(child).map(|v| v.resolve(container.width)),
+            right: Right.get(child).map(|v| v.resolve(container.width)),
+            bottom: Bottom.get(child).map(|v| v.resolve(container.height)),
+        };
+        if insets.top.is_none() && insets.left.is_none() && insets.right.is_none() && insets.bottom.is_none() {
+            None
+        } else {
+            Some(insets)
+        }
+    }
+}
+
+fn is_positioned(child: &dyn Visual) -> bool {
+    Top.get(child).is_some() || Left.get(child).is_some() || Right.get(child).is_some() || Bottom.get(child).is_some()
+}
+
+/// Returns the minimum and maximum length along one axis of an item positioned by its insets.
+///
+/// If both insets are specified, the item is stretched between them.
+fn position_on_axis(start: Option<f64>, end: Option<f64>, container: f64) -> (f64, f64) {
+    match (start, end) {
+        (Some(start), Some(end)) => {
+            let len = (container - start - end).max(0.0);
+            (len, len)
+        }
+        (Some(start), None) => (0.0, (container - start).max(0.0)),
+        (None, Some(end)) => (0.0, (container - end).max(0.0)),
+        (None, None) => (0.0, container),
+    }
+}
+
+/// Stack layout.
+///
+/// Children without insets are laid out with the constraints of the container and aligned within it.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct StackLayout {
+    /// Horizontal alignment of the children without insets.
+    pub horizontal_align: Alignment,
+    /// Vertical alignment of the children without insets.
+    pub vertical_align: Alignment,
+}
+
+impl Layout for StackLayout {
+    fn intrinsic_sizes(&self, children: &[Rc<dyn Visual>]) -> IntrinsicSizes {
+        let mut isizes = IntrinsicSizes::default();
+        for c in children.iter().filter(|c| !is_positioned(&***c)) {
+            let s = c.intrinsic_sizes();
+            isizes.min.width = isizes.min.width.max(s.min.width);
+            isizes.min.height = isizes.min.height.max(s.min.height);
+            isizes.max.width = isizes.max.width.max(s.max.width);
+            isizes.max.height = isizes.max.height.max(s.max.height);
+        }
+        isizes
+    }
+
+    fn intrinsic_height(&self, children: &[Rc<dyn Visual>], width: f64) -> f64 {
+        children
+            .iter()
+            .filter(|c| !is_positioned(&***c))
+            .map(|c| c.intrinsic_height(width))
+            .fold(0.0, f64::max)
+    }
+
+    fn layout(&self, children: &[Rc<dyn Visual>], constraints: &BoxConstraints) -> Geometry {
+        // Lay out the children in the flow first, they determine the size of the stack.
+        let mut content_size = Size::ZERO;
+        let mut flow_geoms = Vec::with_capacity(children.len());
+        for child in children.iter() {
+            if is_positioned(&**child) {
+                flow_geoms.push(None);
+            } else {
+                let g = child.do_layout(&constraints.loosen());
+                content_size.width = content_size.width.max(g.size.width);
+                content_size.height = content_size.height.max(g.size.height);
+                flow_geoms.push(Some(g));
+            }
+        }
+        let size = constraints.constrain(content_size);
+
+        let mut bounding_rect = size.to_rect();
+        let mut paint_bounding_rect = size.to_rect();
+        let mut baseline = None;
+
+        for (child, flow_geom) in children.iter().zip(flow_geoms) {
+            let (geom, offset) = if let Some(g) = flow_geom {
+                let relative = |align: Alignment, container: f64, len: f64| match align {
+                    Alignment::Relative(x) => x * (container - len),
+                    // TODO baseline alignment
+                    _ => 0.0,
+                };
+                let offset = Vec2::new(
+                    relative(self.horizontal_align, size.width, g.size.width),
+                    relative(self.vertical_align, size.height, g.size.height),
+                );
+                if baseline.is_none() {
+                    baseline = g.baseline.map(|b| b + offset.y);
+                }
+                (g, offset)
+            } else {
+                let insets = Insets::get(&**child, size).unwrap();
+                let (min_width, max_width) = position_on_axis(insets.left, insets.right, size.width);
+                let (min_height, max_height) = position_on_axis(insets.top, insets.bottom, size.height);
+                let g = child.do_layout(&BoxConstraints {
+                    min: Size::new(min_width, min_height),
+                    max: Size::new(max_width, max_height),
+                });
+                let x = match (insets.left, insets.right) {
+                    (Some(left), _) => left,
+                    (None, Some(right)) => size.width - right - g.size.width,
+                    (None, None) => 0.0,
+                };
+                let y = match (insets.top, insets.bottom) {
+                    (Some(top), _) => top,
+                    (None, Some(bottom)) => size.height - bottom - g.size.height,
+                    (None, None) => 0.0,
+                };
+                (g, Vec2::new(x, y))
+            };
+            child.set_offset(offset);
+            // positioned children can overflow the container
+            bounding_rect = bounding_rect.union(geom.bounding_rect + offset);
+            paint_bounding_rect = paint_bounding_rect.union(geom.paint_bounding_rect + offset);
+        }
+
+        Geometry {
+            size,
+            baseline,
+            bounding_rect,
+            paint_bounding_rect,
+        }
+    }
+}
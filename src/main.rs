@@ -16,6 +16,8 @@ use crate::window::{Window, WindowOptions};
 mod app_globals;
 mod application;
 mod backend;
+mod clipboard;
+mod command;
 pub mod color;
 mod compositor;
 mod drawing;
@@ -27,6 +29,8 @@ mod paint_ctx;
 mod reactive;
 mod skia_backend;
 mod style;
+#[cfg(feature = "headless")]
+pub mod testing;
 mod text;
 pub mod theme;
 mod widgets;
@@ -8,7 +8,7 @@ mod vulkan;
 #[cfg(feature = "vulkan")]
 pub(crate) use vulkan::*;
 
-#[cfg(feature = "d3d")]
+#[cfg(all(windows, feature = "d3d"))]
 mod d3d;
-#[cfg(feature = "d3d")]
+#[cfg(all(windows, feature = "d3d"))]
 pub(crate) use d3d::*;
@@ -1,5 +1,6 @@
 use std::any::TypeId;
 use std::hash::Hash;
+use std::rc::Rc;
 
 use paste::paste;
 
@@ -42,6 +43,7 @@ impl_style_values!(
     Alignment, Alignment;
     crate::layout::flex::MainAxisAlignment, MainAxisAlignment;
     crate::layout::flex::CrossAxisAlignment, CrossAxisAlignment;
+    crate::layout::flex::FlexWrap, FlexWrap;
     Axis, Axis;
     Color, Color;
     f64, Float;
@@ -51,6 +53,7 @@ impl_style_values!(
     Style, Style;
     Sizing, Sizing;
     Vec<BoxShadow>, BoxShadows;
+    Rc<dyn crate::layout::Layout>, Layout;
     CustomFontAxisValue, CustomFontAxisValue;
 );
 
@@ -102,6 +105,12 @@ style_properties! {
     CrossAxisAlignment: crate::layout::flex::CrossAxisAlignment;
     MainAxisAlignment: crate::layout::flex::MainAxisAlignment;
     FlexFactor: f64;
+    FlexShrink: f64;
+    FlexBasis: f64;
+    AlignSelf: crate::layout::flex::CrossAxisAlignment;
+    FlexWrap: crate::layout::flex::FlexWrap;
+    RowGap: f64;
+    ColumnGap: f64;
     BorderLeft: LengthOrPercentage;
     BorderRight: LengthOrPercentage;
     BorderTop: LengthOrPercentage;
@@ -114,7 +123,7 @@ style_properties! {
     MinHeight: LengthOrPercentage;
     MaxWidth: LengthOrPercentage;
     MaxHeight: LengthOrPercentage;
-
+    Layout: Rc<dyn crate::layout::Layout>;
 
     // Pseudo states
     Active: Style;
@@ -0,0 +1,256 @@
+//! Utilities for testing widgets.
+//!
+//! Golden-image snapshot tests render a visual tree offscreen (with `HeadlessWindow`) and compare the result
+//! against a reference PNG image checked in under `tests/snapshots`.
+//!
+//! When the `KYUTE_UPDATE_SNAPSHOTS` environment variable is set, the reference is (re)written instead of compared.
+//! A missing reference is a failure otherwise. On mismatch, the rendered image and a diff image are written
+//! to `target/snapshots`.
+use std::path::{Path, PathBuf};
+use std::{env, fs};
+
+use kurbo::Size;
+use skia_safe as sk;
+use tracing::info;
+
+use crate::element::Element;
+use crate::window::HeadlessWindow;
+use crate::Color;
+
+/// Options for snapshot tests.
+#[derive(Copy, Clone, Debug)]
+pub struct SnapshotOptions {
+    /// Ratio of physical pixels to logical pixels.
+    pub scale_factor: f64,
+    /// Maximum difference allowed for each color channel of a pixel before it is considered a mismatch.
+    pub tolerance: u8,
+    /// Number of mismatched pixels allowed before the comparison fails.
+    pub max_mismatched_pixels: usize,
+    /// Window background.
+    pub background: Color,
+}
+
+impl Default for SnapshotOptions {
+    fn default() -> Self {
+        SnapshotOptions {
+            scale_factor: 1.0,
+            tolerance: 2,
+            max_mismatched_pixels: 0,
+            background: Color::from_hex("#151515"),
+        }
+    }
+}
+
+/// Decoded RGBA8 (unpremultiplied, sRGB) pixels.
+struct Pixels {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl Pixels {
+    fn read(image: &sk::Image) -> Pixels {
+        let info = sk::ImageInfo::new(
+            image.dimensions(),
+            sk::ColorType::RGBA8888,
+            sk::AlphaType::Unpremul,
+            sk::ColorSpace::new_srgb(),
+        );
+        let row_bytes = info.min_row_bytes();
+        let mut data = vec![0u8; row_bytes * info.height() as usize];
+        let ok = image.read_pixels(&info, &mut data, row_bytes, (0, 0), sk::image::CachingHint::Allow);
+        assert!(ok, "failed to read image pixels");
+        Pixels {
+            width: info.width() as usize,
+            height: info.height() as usize,
+            data,
+        }
+    }
+
+    fn to_png(&self) -> Vec<u8> {
+        let info = sk::ImageInfo::new(
+            (self.width as i32, self.height as i32),
+            sk::ColorType::RGBA8888,
+            sk::AlphaType::Unpremul,
+            sk::ColorSpace::new_srgb(),
+        );
+        let image = sk::images::raster_from_data(&info, sk::Data::new_copy(&self.data), self.width * 4)
+            .expect("failed to create image from pixels");
+        encode_png(&image)
+    }
+}
+
+fn encode_png(image: &sk::Image) -> Vec<u8> {
+    image
+        .encode(None, sk::EncodedImageFormat::PNG, None)
+        .expect("failed to encode PNG image")
+        .as_bytes()
+        .to_vec()
+}
+
+fn decode_png(data: &[u8]) -> Option<sk::Image> {
+    sk::Image::from_encoded(sk::Data::new_copy(data))
+}
+
+/// Result of the comparison of two images.
+struct Comparison {
+    mismatched_pixels: usize,
+    diff: Pixels,
+}
+
+/// Compares two images of the same size pixel by pixel.
+///
+/// In the diff image, mismatched pixels are red and the others are a faded version of the expected image.
+fn compare(expected: &Pixels, actual: &Pixels, tolerance: u8) -> Comparison {
+    let mut mismatched_pixels = 0;
+    let mut diff = Vec::with_capacity(expected.data.len());
+    for (e, a) in expected.data.chunks_exact(4).zip(actual.data.chunks_exact(4)) {
+        let max_delta = e.iter().zip(a).map(|(e, a)| e.abs_diff(*a)).max().unwrap();
+        if max_delta > tolerance {
+            mismatched_pixels += 1;
+            diff.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let luma = (e[0] as u32 * 299 + e[1] as u32 * 587 + e[2] as u32 * 114) / 1000;
+            let faded = (luma / 4) as u8;
+            diff.extend_from_slice(&[faded, faded, faded, 255]);
+        }
+    }
+    Comparison {
+        mismatched_pixels,
+        diff: Pixels {
+            width: expected.width,
+            height: expected.height,
+            data: diff,
+        },
+    }
+}
+
+fn reference_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/snapshots")
+        .join(format!("{name}.png"))
+}
+
+fn output_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("target/snapshots")
+}
+
+fn write_file(path: &Path, data: &[u8]) {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).expect("failed to create snapshot directory");
+    }
+    fs::write(path, data).unwrap_or_else(|err| panic!("failed to write `{}`: {err}", path.display()));
+}
+
+/// Renders a visual tree offscreen with the specified logical size.
+pub fn render(root: &Element, size: Size, options: &SnapshotOptions) -> sk::Image {
+    let window = HeadlessWindow::new(root, size, options.scale_factor);
+    window.set_background(options.background);
+    window.render()
+}
+
+/// Renders a visual tree to a PNG image.
+pub fn render_to_png(root: &Element, size: Size, options: &SnapshotOptions) -> Vec<u8> {
+    encode_png(&render(root, size, options))
+}
+
+/// Renders a visual tree and compares it against the reference image with the specified name.
+///
+/// # Panics
+///
+/// If the rendered image doesn't match the reference image, or if the reference image doesn't exist
+/// (unless `KYUTE_UPDATE_SNAPSHOTS` is set).
+pub fn assert_snapshot(name: &str, root: &Element, size: Size, options: &SnapshotOptions) {
+    let image = render(root, size, options);
+    let actual = Pixels::read(&image);
+    let reference_path = reference_path(name);
+
+    if env::var_os("KYUTE_UPDATE_SNAPSHOTS").is_some() {
+        info!("writing reference snapshot `{}`", reference_path.display());
+        write_file(&reference_path, &encode_png(&image));
+        return;
+    }
+
+    let reference = fs::read(&reference_path).unwrap_or_else(|err| {
+        let actual_path = output_dir().join(format!("{name}.actual.png"));
+        write_file(&actual_path, &encode_png(&image));
+        panic!(
+            "missing reference snapshot `{}` ({err}); rendered image written to `{}`, \
+             run with KYUTE_UPDATE_SNAPSHOTS=1 to accept it",
+            reference_path.display(),
+            actual_path.display()
+        )
+    });
+
+    let expected = Pixels::read(&decode_png(&reference).expect("failed to decode reference snapshot"));
+
+    let failure = if (expected.width, expected.height) != (actual.width, actual.height) {
+        Some(format!(
+            "size mismatch: expected {}x{}, got {}x{}",
+            expected.width, expected.height, actual.width, actual.height
+        ))
+    } else {
+        let comparison = compare(&expected, &actual, options.tolerance);
+        if comparison.mismatched_pixels > options.max_mismatched_pixels {
+            write_file(&output_dir().join(format!("{name}.diff.png")), &comparison.diff.to_png());
+            Some(format!("{} pixels differ", comparison.mismatched_pixels))
+        } else {
+            None
+        }
+    };
+
+    if let Some(failure) = failure {
+        let actual_path = output_dir().join(format!("{name}.actual.png"));
+        write_file(&actual_path, &encode_png(&image));
+        panic!(
+            "snapshot `{name}` doesn't match the reference image: {failure} (rendered image written to `{}`)",
+            actual_path.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use kurbo::Vec2;
+
+    use super::*;
+    use crate::drawing::BoxShadow;
+    use crate::layout::Sizing;
+    use crate::style::{Style, StyleExt};
+    use crate::widgets::frame::Frame;
+
+    // The reference image must be rendered with `KYUTE_UPDATE_SNAPSHOTS=1 cargo test -- --ignored`, checked
+    // visually and committed to `tests/snapshots` before this test can be enabled.
+    #[test]
+    #[ignore = "reference image `tests/snapshots/frame_with_border_and_shadow.png` not generated yet"]
+    fn frame_with_border_and_shadow() {
+        let frame = Frame::new(
+            Style::new()
+                .background_color(Color::from_hex("211e13"))
+                .border_radius(8.0)
+                .width(Sizing::Length(120.0.into()))
+                .height(Sizing::Length(60.0.into()))
+                .border_color(Color::from_hex("5f5637"))
+                .border_left(1.0.into())
+                .border_right(1.0.into())
+                .border_top(1.0.into())
+                .border_bottom(1.0.into())
+                .box_shadows(vec![BoxShadow {
+                    color: Color::from_hex("000000"),
+                    offset: Vec2::new(0.0, 2.0),
+                    blur: 4.0,
+                    spread: 0.0,
+                    inset: false,
+                }]),
+        );
+        assert_snapshot(
+            "frame_with_border_and_shadow",
+            &frame,
+            Size::new(160.0, 100.0),
+            &SnapshotOptions {
+                scale_factor: 2.0,
+                ..Default::default()
+            },
+        );
+    }
+}
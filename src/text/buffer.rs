@@ -0,0 +1,244 @@
+//! Storage for editable text.
+use std::fmt;
+use std::ops::Range;
+
+use ropey::Rope;
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete};
+
+/// A change to the contents of a `TextBuffer`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TextChange {
+    /// Byte offset of the change.
+    pub offset: usize,
+    /// Text removed at `offset`.
+    pub removed: String,
+    /// Text inserted at `offset`.
+    pub inserted: String,
+}
+
+impl TextChange {
+    /// Returns the change that reverts this one.
+    pub fn inverse(&self) -> TextChange {
+        TextChange {
+            offset: self.offset,
+            removed: self.inserted.clone(),
+            inserted: self.removed.clone(),
+        }
+    }
+}
+
+fn is_line_break(ch: char) -> bool {
+    matches!(ch, '\n' | '\r' | '\u{0B}' | '\u{0C}' | '\u{85}' | '\u{2028}' | '\u{2029}')
+}
+
+/// Returns the length in bytes of the word at the start of `chars`, including leading whitespace.
+fn word_len(chars: impl Iterator<Item = char>) -> usize {
+    enum State {
+        LeadingWhitespace,
+        Alnum,
+        NotAlnum,
+    }
+    let mut len = 0;
+    let mut state = State::LeadingWhitespace;
+    for ch in chars {
+        match state {
+            State::LeadingWhitespace => {
+                if !ch.is_whitespace() {
+                    if ch.is_alphanumeric() {
+                        state = State::Alnum;
+                    } else {
+                        state = State::NotAlnum;
+                    }
+                }
+            }
+            State::Alnum => {
+                if !ch.is_alphanumeric() {
+                    return len;
+                }
+            }
+            State::NotAlnum => {
+                return len;
+            }
+        }
+        len += ch.len_utf8();
+    }
+    len
+}
+
+/// Rope-backed text storage.
+///
+/// Edits don't move the rest of the text, and navigation (lines, graphemes, words) doesn't need a contiguous copy of
+/// it. Note that `TextEdit` still lays out its whole text as a single paragraph on every change.
+///
+/// Offsets are in bytes, and must fall on char boundaries.
+#[derive(Clone, Default)]
+pub struct TextBuffer {
+    rope: Rope,
+}
+
+impl TextBuffer {
+    pub fn new() -> TextBuffer {
+        TextBuffer::default()
+    }
+
+    /// Returns the length of the text in bytes.
+    pub fn len(&self) -> usize {
+        self.rope.len_bytes()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rope.len_bytes() == 0
+    }
+
+    /// Returns the number of chars in the text.
+    pub fn char_count(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    /// Returns the text in the specified byte range.
+    pub fn slice(&self, range: Range<usize>) -> String {
+        self.rope.byte_slice(range).to_string()
+    }
+
+    /// Appends the text in the specified byte range to `out`.
+    pub fn push_slice_to(&self, range: Range<usize>, out: &mut String) {
+        for chunk in self.rope.byte_slice(range).chunks() {
+            out.push_str(chunk);
+        }
+    }
+
+    /// Returns whether the text contains a line break.
+    pub fn is_multiline(&self) -> bool {
+        self.rope.len_lines() > 1
+    }
+
+    /// Replaces a range of the text, and returns the corresponding change.
+    pub fn replace(&mut self, range: Range<usize>, text: &str) -> TextChange {
+        let change = TextChange {
+            offset: range.start,
+            removed: self.slice(range),
+            inserted: text.to_string(),
+        };
+        self.apply(&change);
+        change
+    }
+
+    /// Applies a change to the text.
+    pub fn apply(&mut self, change: &TextChange) {
+        let start = self.rope.byte_to_char(change.offset);
+        let end = self.rope.byte_to_char(change.offset + change.removed.len());
+        self.rope.remove(start..end);
+        self.rope.insert(start, &change.inserted);
+    }
+
+    /// Returns the offset of the start of the line containing `offset`.
+    pub fn line_start(&self, offset: usize) -> usize {
+        self.rope.line_to_byte(self.rope.byte_to_line(offset))
+    }
+
+    /// Returns the offset of the end of the line containing `offset`, before the line break.
+    pub fn line_end(&self, offset: usize) -> usize {
+        let line_index = self.rope.byte_to_line(offset);
+        let line = self.rope.line(line_index);
+        let line_break_len: usize = line
+            .chars()
+            .reversed()
+            .take_while(|ch| is_line_break(*ch))
+            .map(char::len_utf8)
+            .sum();
+        self.rope.line_to_byte(line_index) + line.len_bytes() - line_break_len
+    }
+
+    /// Returns the offset of the grapheme cluster boundary after `offset`, or `None` at the end of the text.
+    pub fn next_grapheme_boundary(&self, offset: usize) -> Option<usize> {
+        let (mut chunk, mut chunk_start, _, _) = self.rope.chunk_at_byte(offset);
+        let mut cursor = GraphemeCursor::new(offset, self.len(), true);
+        loop {
+            match cursor.next_boundary(chunk, chunk_start) {
+                Ok(boundary) => return boundary,
+                Err(GraphemeIncomplete::NextChunk) => {
+                    chunk_start += chunk.len();
+                    chunk = self.rope.chunk_at_byte(chunk_start).0;
+                }
+                Err(GraphemeIncomplete::PreContext(end)) => {
+                    let context = self.rope.chunk_at_byte(end - 1).0;
+                    cursor.provide_context(context, end - context.len());
+                }
+                Err(err) => unreachable!("unexpected grapheme cursor state: {err:?}"),
+            }
+        }
+    }
+
+    /// Returns the offset of the grapheme cluster boundary before `offset`, or `None` at the start of the text.
+    pub fn prev_grapheme_boundary(&self, offset: usize) -> Option<usize> {
+        let (mut chunk, mut chunk_start, _, _) = self.rope.chunk_at_byte(offset);
+        let mut cursor = GraphemeCursor::new(offset, self.len(), true);
+        loop {
+            match cursor.prev_boundary(chunk, chunk_start) {
+                Ok(boundary) => return boundary,
+                Err(GraphemeIncomplete::PrevChunk) => {
+                    let (prev_chunk, prev_chunk_start, _, _) = self.rope.chunk_at_byte(chunk_start - 1);
+                    chunk = prev_chunk;
+                    chunk_start = prev_chunk_start;
+                }
+                Err(GraphemeIncomplete::PreContext(end)) => {
+                    let context = self.rope.chunk_at_byte(end - 1).0;
+                    cursor.provide_context(context, end - context.len());
+                }
+                Err(err) => unreachable!("unexpected grapheme cursor state: {err:?}"),
+            }
+        }
+    }
+
+    /// Returns the offset of the end of the word after `offset`.
+    pub fn next_word_boundary(&self, offset: usize) -> usize {
+        offset + word_len(self.rope.chars_at(self.rope.byte_to_char(offset)))
+    }
+
+    /// Returns the offset of the start of the word before `offset`.
+    pub fn prev_word_boundary(&self, offset: usize) -> usize {
+        offset - word_len(self.rope.chars_at(self.rope.byte_to_char(offset)).reversed())
+    }
+}
+
+impl From<&str> for TextBuffer {
+    fn from(text: &str) -> Self {
+        TextBuffer { rope: Rope::from_str(text) }
+    }
+}
+
+impl fmt::Display for TextBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.rope.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for TextBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TextBuffer({:?})", self.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_and_navigate() {
+        let mut buffer = TextBuffer::from("héllo wörld\nnext line");
+        assert_eq!(buffer.line_end(0), "héllo wörld".len());
+        assert_eq!(buffer.line_start(buffer.len()), "héllo wörld\n".len());
+        assert_eq!(buffer.next_grapheme_boundary(1), Some(3));
+        assert_eq!(buffer.prev_grapheme_boundary(3), Some(1));
+        assert_eq!(buffer.next_word_boundary(0), "héllo".len());
+        assert_eq!(buffer.prev_word_boundary("héllo wörld".len()), "héllo ".len());
+
+        let change = buffer.replace(0..1, "J");
+        assert_eq!(buffer.to_string(), "Jéllo wörld\nnext line");
+        buffer.apply(&change.inverse());
+        assert_eq!(buffer.to_string(), "héllo wörld\nnext line");
+    }
+}
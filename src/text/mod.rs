@@ -16,6 +16,10 @@ use crate::drawing::{FromSkia, ToSkia};
 use crate::style::{style_properties, Style};
 use crate::Color;
 
+mod buffer;
+
+pub use buffer::{TextBuffer, TextChange};
+
 thread_local! {
     static FONT_COLLECTION: OnceCell<FontCollection> = OnceCell::new();
 }
@@ -421,6 +425,34 @@ fn test_text() {
     );
 }
 
+/// Base direction of a paragraph.
+///
+/// It determines the alignment of the lines and the visual order of runs of mixed-direction text
+/// (e.g. Latin numbers or names in Arabic or Hebrew text).
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum TextDirection {
+    #[default]
+    LeftToRight,
+    RightToLeft,
+}
+
+impl TextDirection {
+    pub fn is_rtl(self) -> bool {
+        self == TextDirection::RightToLeft
+    }
+}
+
+impl ToSkia for TextDirection {
+    type Target = sk::textlayout::TextDirection;
+
+    fn to_skia(&self) -> Self::Target {
+        match self {
+            TextDirection::LeftToRight => sk::textlayout::TextDirection::LTR,
+            TextDirection::RightToLeft => sk::textlayout::TextDirection::RTL,
+        }
+    }
+}
+
 /// Lines of formatted (shaped and layouted) text.
 pub struct FormattedText {
     pub inner: skia_safe::textlayout::Paragraph,
@@ -443,11 +475,20 @@ impl FormattedText {
     // With IntoIterator this works with everything (there are no slices involved)
 
     pub fn new<'a>(text: impl IntoIterator<Item=AttributedRange<'a>>) -> Self {
+        Self::with_base_direction(text, TextDirection::LeftToRight)
+    }
+
+    /// Creates a new formatted text object with the specified paragraph base direction.
+    pub fn with_base_direction<'a>(
+        text: impl IntoIterator<Item = AttributedRange<'a>>,
+        base_direction: TextDirection,
+    ) -> Self {
         let font_collection = get_font_collection();
         let mut text_style = sk::textlayout::TextStyle::new();
         text_style.set_font_size(16.0 as sk::scalar); // TODO default font size
         let mut paragraph_style = sk::textlayout::ParagraphStyle::new();
         paragraph_style.set_text_style(&text_style);
+        paragraph_style.set_text_direction(base_direction.to_skia());
         let mut builder = sk::textlayout::ParagraphBuilder::new(&paragraph_style, font_collection);
 
         for run in text.into_iter() {
@@ -474,6 +515,16 @@ impl FormattedText {
         let text_boxes = self.inner.get_rects_for_range(range, RectHeightStyle::Tight, RectWidthStyle::Tight);
         text_boxes.iter().map(|r| Rect::from_skia(r.rect)).collect()
     }
+
+    /// Returns the rectangles to highlight when the specified range of text is selected.
+    ///
+    /// If the range spans runs of different directions, there is one rectangle for each run, and they may not be
+    /// contiguous on screen. Unlike `get_rects_for_range`, rectangles span the full height of their line, so that
+    /// runs in different fonts are highlighted evenly.
+    pub fn get_selection_rects(&self, range: Range<usize>) -> Vec<Rect> {
+        let text_boxes = self.inner.get_rects_for_range(range, RectHeightStyle::Max, RectWidthStyle::Tight);
+        text_boxes.iter().map(|r| Rect::from_skia(r.rect)).collect()
+    }
 }
 
 /// Text selection.
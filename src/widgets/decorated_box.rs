@@ -5,8 +5,8 @@ use std::rc::Rc;
 use kurbo::{Point, Size};
 use crate::drawing::Decoration;
 use crate::element::{AnyVisual, Element, Visual};
-use crate::event::Event;
-use crate::layout::{BoxConstraints, Geometry};
+use crate::event::{Event, EventCtx};
+use crate::layout::{BoxConstraints, Geometry, IntrinsicSizes};
 use crate::paint_ctx::PaintCtx;
 
 pub struct DecoratedBox<D> {
@@ -40,6 +40,17 @@ impl<D: Decoration + 'static> Visual for DecoratedBox<D> {
         &self.element
     }
 
+    fn intrinsic_sizes(&self) -> IntrinsicSizes {
+        IntrinsicSizes {
+            min: self.size.get(),
+            max: self.size.get(),
+        }
+    }
+
+    fn intrinsic_height(&self, _width: f64) -> f64 {
+        self.size.get().height
+    }
+
     fn layout(&self, children: &[Rc<dyn Visual>], constraints: &BoxConstraints) -> Geometry {
         // stack children
         for child in children {
@@ -64,7 +75,7 @@ impl<D: Decoration + 'static> Visual for DecoratedBox<D> {
         });
     }
 
-    async fn event(&self, event: &mut Event)
+    async fn event(&self, _ctx: &mut EventCtx, _event: &mut Event)
     where
         Self: Sized
     {}
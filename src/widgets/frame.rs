@@ -10,10 +10,13 @@ use tracing::warn;
 
 use crate::drawing::{BoxShadow, Paint, ToSkia};
 use crate::element::{AnyVisual, Element, Visual};
-use crate::event::Event;
+use crate::event::{Event, EventCtx};
 use crate::handler::Handler;
-use crate::layout::flex::{do_flex_layout, Axis, CrossAxisAlignment, FlexLayoutParams, MainAxisAlignment};
-use crate::layout::{place_child_box, Alignment, BoxConstraints, Geometry, LengthOrPercentage, Sizing, IntrinsicSizes};
+use crate::element::AttachedProperty;
+use crate::layout::flex::{Axis, CrossAxisAlignment, FlexLayout, FlexWrap, MainAxisAlignment};
+use crate::layout::{
+    place_child_box, Alignment, BoxConstraints, Geometry, IntrinsicSizes, Layout, LengthOrPercentage, Sizing,
+};
 use crate::style::{
     Active, BackgroundColor, Baseline, BorderBottom, BorderColor, BorderLeft, BorderRadius, BorderRight, BorderTop,
     BoxShadows, Direction, Focus, Height, HorizontalAlign, Hover, MaxHeight, MaxWidth, MinHeight, MinWidth,
@@ -43,10 +46,18 @@ pub struct ResolvedFrameStyle {
     direction: Axis,
     main_axis_alignment: MainAxisAlignment,
     cross_axis_alignment: CrossAxisAlignment,
+    wrap: FlexWrap,
+    row_gap: f64,
+    column_gap: f64,
     min_width: Option<LengthOrPercentage>,
     max_width: Option<LengthOrPercentage>,
     min_height: Option<LengthOrPercentage>,
     max_height: Option<LengthOrPercentage>,
+    layout: Option<Rc<dyn Layout>>,
+    flex_factor: Option<f64>,
+    flex_shrink: Option<f64>,
+    flex_basis: Option<f64>,
+    align_self: Option<CrossAxisAlignment>,
 }
 
 #[derive(Copy, Clone, Debug, Default)]
@@ -54,6 +65,8 @@ pub struct InteractState {
     pub active: bool,
     pub hovered: bool,
     pub focused: bool,
+    /// Whether the focus was moved to the frame with the keyboard (see `Element::has_focus_visible`).
+    pub focus_visible: bool,
 }
 
 /// A container with a fixed width and height, into which a unique widget is placed.
@@ -69,6 +82,8 @@ pub struct Frame {
     style_changed: Cell<bool>,
     state_affects_style: Cell<bool>,
     resolved_style: RefCell<ResolvedFrameStyle>,
+    /// Layout of the children, overrides the one specified in the style.
+    layout: RefCell<Option<Rc<dyn Layout>>>,
 }
 
 impl Deref for Frame {
@@ -82,7 +97,7 @@ impl Deref for Frame {
 impl Frame {
     /// Creates a new `Frame` with the given decoration.
     pub fn new(style: Style) -> Rc<Frame> {
-        Element::new_derived(|element| Frame {
+        let frame = Element::new_derived(|element| Frame {
             element,
             clicked: Default::default(),
             hovered: Default::default(),
@@ -94,13 +109,72 @@ impl Frame {
             style_changed: Cell::new(true),
             state_affects_style: Cell::new(false),
             resolved_style: Default::default(),
-        })
+            layout: Default::default(),
+        });
+        // resolve the style now so that the flex item properties are set before the parent is laid out
+        frame.calculate_style();
+        frame
+    }
+
+    /// Updates the attached properties read by a parent flex layout from the resolved style of this frame.
+    ///
+    /// Properties specified by the previous style but not by the current one are removed.
+    fn set_flex_item_properties(&self, previous: &ResolvedFrameStyle, current: &ResolvedFrameStyle) {
+        use crate::layout::flex;
+
+        fn update<P: AttachedProperty>(
+            item: &dyn Visual,
+            property: P,
+            previous: Option<P::Value>,
+            current: Option<P::Value>,
+        ) {
+            match current {
+                Some(value) => property.set(item, value),
+                None if previous.is_some() => property.unset(item),
+                None => {}
+            }
+        }
+
+        let this = self as &dyn Visual;
+        update(this, flex::FlexFactor, previous.flex_factor, current.flex_factor);
+        update(this, flex::FlexShrink, previous.flex_shrink, current.flex_shrink);
+        update(this, flex::FlexBasis, previous.flex_basis, current.flex_basis);
+        update(this, flex::AlignSelf, previous.align_self, current.align_self);
     }
 
     pub fn set_content(&self, content: &dyn Visual) {
         (self as &dyn Visual).add_child(content);
     }
 
+    /// Sets the layout of the children of this frame.
+    ///
+    /// This takes precedence over the layout specified in the style.
+    pub fn set_layout(&self, layout: impl Layout + 'static) {
+        self.layout.replace(Some(Rc::new(layout)));
+        self.mark_needs_relayout();
+    }
+
+    /// Returns the layout used for the children of this frame.
+    ///
+    /// By order of priority: the layout set with `set_layout`, the layout in the style, or a flex layout
+    /// with the direction and alignments specified in the style.
+    fn content_layout(&self, s: &ResolvedFrameStyle) -> Rc<dyn Layout> {
+        if let Some(layout) = self.layout.borrow().clone() {
+            return layout;
+        }
+        if let Some(ref layout) = s.layout {
+            return layout.clone();
+        }
+        Rc::new(FlexLayout {
+            axis: s.direction,
+            main_axis_alignment: s.main_axis_alignment,
+            cross_axis_alignment: s.cross_axis_alignment,
+            wrap: s.wrap,
+            row_gap: s.row_gap,
+            column_gap: s.column_gap,
+        })
+    }
+
     pub async fn clicked(&self) {
         self.clicked.wait().await;
     }
@@ -112,7 +186,8 @@ impl Frame {
             let mut state_affects_style = false;
 
             if let Some(focused) = self.style.get(Focus) {
-                if state.focused {
+                // only show the focus style after keyboard navigation, like `:focus-visible`
+                if state.focus_visible {
                     s = focused.over(s);
                 }
                 state_affects_style = true;
@@ -130,8 +205,7 @@ impl Frame {
                 state_affects_style = true;
             }
 
-            let mut r = self.resolved_style.borrow_mut();
-            *r = ResolvedFrameStyle {
+            let resolved = ResolvedFrameStyle {
                 padding_left: s.get_or_default(PaddingLeft),
                 padding_right: s.get_or_default(PaddingRight),
                 padding_top: s.get_or_default(PaddingTop),
@@ -152,11 +226,21 @@ impl Frame {
                 direction: s.get_or_default(Direction),
                 main_axis_alignment: s.get_or_default(style::MainAxisAlignment),
                 cross_axis_alignment: s.get_or_default(style::CrossAxisAlignment),
+                wrap: s.get_or_default(style::FlexWrap),
+                row_gap: s.get_or_default(style::RowGap),
+                column_gap: s.get_or_default(style::ColumnGap),
                 min_width: s.get(MinWidth),
                 max_width: s.get(MaxWidth),
                 min_height: s.get(MinHeight),
                 max_height: s.get(MaxHeight),
+                layout: s.get(style::Layout),
+                flex_factor: s.get(style::FlexFactor),
+                flex_shrink: s.get(style::FlexShrink),
+                flex_basis: s.get(style::FlexBasis),
+                align_self: s.get(style::AlignSelf),
             };
+            let previous = self.resolved_style.replace(resolved);
+            self.set_flex_item_properties(&previous, &self.resolved_style.borrow());
 
             self.state_affects_style.set(state_affects_style);
             self.style_changed.set(false);
@@ -233,33 +317,43 @@ impl FrameSizes {
     }
 }
 
-fn compute_intrinsic_sizes(direction: Axis, children: &[Rc<dyn Visual>]) -> IntrinsicSizes {
-    let mut isizes = IntrinsicSizes::default();
-    for c in children.iter() {
-        let s = c.intrinsic_sizes();
-        match direction {
-            Axis::Horizontal => {
-                // horizontal layout
-                // width is sum of all children
-                // height is max of all children
-                isizes.min.width += s.min.width;
-                isizes.max.width += s.max.width;
-                isizes.min.height = isizes.min.height.max(s.min.height);
-                isizes.max.height = isizes.max.height.max(s.max.height);
+/// Clamps a length between a minimum and a maximum, ignoring the bounds that are percentages
+/// (they can't be resolved when computing intrinsic sizes).
+fn clamp_definite(len: f64, min: Option<LengthOrPercentage>, max: Option<LengthOrPercentage>) -> f64 {
+    let mut len = len;
+    if let Some(LengthOrPercentage::Px(max)) = max {
+        len = len.min(max);
+    }
+    if let Some(LengthOrPercentage::Px(min)) = min {
+        len = len.max(min);
+    }
+    len
+}
 
-            }
-            Axis::Vertical => {
-                // vertical layout
-                // width is max of all children
-                // height is sum of all children
-                isizes.min.height += s.min.height;
-                isizes.max.height += s.max.height;
-                isizes.min.width = isizes.min.width.max(s.min.width);
-                isizes.max.width = isizes.max.width.max(s.max.width);
-            }
-        }
+impl ResolvedFrameStyle {
+    /// Returns the total horizontal and vertical insets (padding and border) of the frame, for the computation
+    /// of intrinsic sizes. Percentages resolve to zero.
+    fn intrinsic_insets(&self) -> (f64, f64) {
+        let horizontal = self.padding_left.resolve(0.0)
+            + self.padding_right.resolve(0.0)
+            + self.border_left.resolve(0.0)
+            + self.border_right.resolve(0.0);
+        let vertical = self.padding_top.resolve(0.0)
+            + self.padding_bottom.resolve(0.0)
+            + self.border_top.resolve(0.0)
+            + self.border_bottom.resolve(0.0);
+        (horizontal, vertical)
+    }
+
+    /// Returns the height of the frame for the specified frame width.
+    fn intrinsic_height(&self, layout: &dyn Layout, children: &[Rc<dyn Visual>], width: f64) -> f64 {
+        let (horizontal_insets, vertical_insets) = self.intrinsic_insets();
+        let height = match self.height {
+            Some(Sizing::Length(LengthOrPercentage::Px(height))) => height,
+            _ => layout.intrinsic_height(children, (width - horizontal_insets).max(0.0)) + vertical_insets,
+        };
+        clamp_definite(height, self.min_height, self.max_height)
     }
-    isizes
 }
 
 impl Visual for Frame {
@@ -267,20 +361,61 @@ impl Visual for Frame {
         &self.element
     }
 
+    fn intrinsic_sizes(&self) -> IntrinsicSizes {
+        self.calculate_style();
+        let s = self.resolved_style.borrow();
+        let layout = self.content_layout(&s);
+        let children = self.children();
+        let content = layout.intrinsic_sizes(&children);
+        let (horizontal_insets, vertical_insets) = s.intrinsic_insets();
+
+        let width = |content_width: f64| {
+            let width = match s.width {
+                Some(Sizing::Length(LengthOrPercentage::Px(width))) => width,
+                Some(Sizing::MinContent) => content.min.width + horizontal_insets,
+                Some(Sizing::MaxContent) => content.max.width + horizontal_insets,
+                _ => content_width + horizontal_insets,
+            };
+            clamp_definite(width, s.min_width, s.max_width)
+        };
+        let min_width = width(content.min.width);
+        let max_width = width(content.max.width);
+
+        let min_height = s.intrinsic_height(&*layout, &children, min_width);
+        let max_height = s.intrinsic_height(&*layout, &children, max_width);
+        IntrinsicSizes {
+            min: Size::new(min_width, min_height),
+            max: Size::new(max_width, max_height),
+        }
+    }
+
+    fn intrinsic_height(&self, width: f64) -> f64 {
+        self.calculate_style();
+        let s = self.resolved_style.borrow();
+        let layout = self.content_layout(&s);
+        let width = match s.width {
+            Some(Sizing::Length(LengthOrPercentage::Px(width))) => width,
+            _ => width,
+        };
+        let width = clamp_definite(width, s.min_width, s.max_width);
+        s.intrinsic_height(&*layout, &self.children(), width)
+    }
+
     fn layout(&self, children: &[Rc<dyn Visual>], constraints: &BoxConstraints) -> Geometry {
         self.calculate_style();
         let s = self.resolved_style.borrow();
+        let layout = self.content_layout(&s);
 
         let max_width = constraints.max.width;
         let max_height = constraints.max.height;
 
         let mut intrinsic_sizes = IntrinsicSizes::default();
-        if matches!(s.width, Some(Sizing::MaxContent | Sizing::MinContent)) ||
-            matches!(s.height, Some(Sizing::MaxContent | Sizing::MinContent)) {
+        if matches!(s.width, Some(Sizing::MaxContent | Sizing::MinContent)) {
             // we need to compute the intrinsic size of the content
-            intrinsic_sizes = compute_intrinsic_sizes(s.direction, children);
+            intrinsic_sizes = layout.intrinsic_sizes(children);
         }
 
+        // Borders are drawn inside the frame, so the content is inset by both the padding and the border.
         let horizontal = FrameSizes {
             parent_min: constraints.min.width,
             parent_max: constraints.max.width,
@@ -289,23 +424,33 @@ impl Visual for Frame {
             self_min: s.min_width.map(|w| w.resolve(max_width)),
             self_max: s.max_width.map(|w| w.resolve(max_width)),
             fixed: s.width,
-            padding_before: s.padding_left.resolve(max_width),
-            padding_after: s.padding_right.resolve(max_width),
+            padding_before: s.padding_left.resolve(max_width) + s.border_left.resolve(max_width),
+            padding_after: s.padding_right.resolve(max_width) + s.border_right.resolve(max_width),
         };
+        let (child_min_width, child_max_width) = horizontal.compute_child_constraint();
+
+        // The height of the content depends on the width available to it.
+        let mut content_height = 0.0;
+        if matches!(s.height, Some(Sizing::MaxContent | Sizing::MinContent)) {
+            let content_width = if child_max_width.is_finite() {
+                child_max_width
+            } else {
+                layout.intrinsic_sizes(children).max.width
+            };
+            content_height = layout.intrinsic_height(children, content_width);
+        }
 
         let vertical = FrameSizes {
             parent_min: constraints.min.height,
             parent_max: constraints.max.height,
-            content_min: intrinsic_sizes.min.height,
-            content_max: intrinsic_sizes.max.height,
+            content_min: content_height,
+            content_max: content_height,
             self_min: s.min_height.map(|h| h.resolve(max_height)),
             self_max: s.max_height.map(|h| h.resolve(max_height)),
             fixed: s.height,
-            padding_before: s.padding_top.resolve(max_height),
-            padding_after: s.padding_bottom.resolve(max_height),
+            padding_before: s.padding_top.resolve(max_height) + s.border_top.resolve(max_height),
+            padding_after: s.padding_bottom.resolve(max_height) + s.border_bottom.resolve(max_height),
         };
-
-        let (child_min_width, child_max_width) = horizontal.compute_child_constraint();
         let (child_min_height, child_max_height) = vertical.compute_child_constraint();
 
         let child_constraints = BoxConstraints {
@@ -314,14 +459,7 @@ impl Visual for Frame {
         };
 
         // layout children
-        // TODO other layouts
-        let flex_params = FlexLayoutParams {
-            axis: s.direction,
-            constraints: child_constraints,
-            cross_axis_alignment: s.cross_axis_alignment,
-            main_axis_alignment: s.main_axis_alignment,
-        };
-        let child_geom = do_flex_layout(&flex_params, children);
+        let child_geom = layout.layout(children, &child_constraints);
 
         // child geometry is determined, now determine our size
         let self_width = horizontal.compute_self_size(child_geom.size.width);
@@ -358,8 +496,10 @@ impl Visual for Frame {
         Geometry {
             size,
             baseline: Some(baseline),
-            bounding_rect: size.to_rect(),       // TODO
-            paint_bounding_rect: size.to_rect(), // TODO
+            // include overflowing content
+            bounding_rect: size.to_rect().union(child_geom.bounding_rect + offset),
+            // TODO shadows
+            paint_bounding_rect: size.to_rect().union(child_geom.paint_bounding_rect + offset),
         }
     }
 
@@ -406,7 +546,7 @@ impl Visual for Frame {
         });
     }
 
-    async fn event(&self, event: &mut Event)
+    async fn event(&self, _ctx: &mut EventCtx, event: &mut Event)
     where
         Self: Sized,
     {
@@ -415,6 +555,8 @@ impl Visual for Frame {
             this.state_changed.emit(state).await;
             if this.state_affects_style.get() {
                 this.style_changed.set(true);
+                // update the flex item properties before the parent is laid out again
+                this.calculate_style();
                 this.mark_needs_relayout();
             }
         }
@@ -443,6 +585,18 @@ impl Visual for Frame {
                 update_state(self, state).await;
                 self.hovered.emit(false).await;
             }
+            Event::FocusGained => {
+                state.focused = true;
+                state.focus_visible = self.has_focus_visible();
+                update_state(self, state).await;
+                self.focused.emit(true).await;
+            }
+            Event::FocusLost => {
+                state.focused = false;
+                state.focus_visible = false;
+                update_state(self, state).await;
+                self.focused.emit(false).await;
+            }
             _ => {}
         }
     }
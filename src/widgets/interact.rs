@@ -2,7 +2,7 @@
 use std::cell::Cell;
 use std::rc::Rc;
 use crate::element::{AnyVisual, Element, Visual};
-use crate::event::Event;
+use crate::event::{Event, EventCtx};
 use crate::handler::Handler;
 
 #[derive(Copy,Clone,Debug, Default)]
@@ -56,7 +56,7 @@ impl Visual for Interact {
     }
 
 
-    async fn event(&self, event: &mut Event)
+    async fn event(&self, _ctx: &mut EventCtx, event: &mut Event)
     where
         Self: Sized
     {
@@ -0,0 +1,477 @@
+//! Virtualized list of items.
+
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::ops::Deref;
+use std::rc::{Rc, Weak};
+
+use keyboard_types::Key;
+use kurbo::{Point, Rect, Size, Vec2};
+use skia_safe as sk;
+
+use crate::application::spawn;
+use crate::drawing::ToSkia;
+use crate::element::{Element, Visual};
+use crate::event::{Event, EventCtx};
+use crate::handler::Handler;
+use crate::layout::{BoxConstraints, Geometry, IntrinsicSizes};
+use crate::theme::DARK_THEME;
+use crate::{Color, PaintCtx};
+
+/// An item element currently in the list.
+struct RealizedItem<V> {
+    /// Index of the item it displays.
+    index: usize,
+    element: Rc<V>,
+    /// Vertical position of the element in the list.
+    y: f64,
+    height: f64,
+}
+
+/// A vertical list that only creates elements for the items that are visible.
+///
+/// Elements are created with the `create` function passed to `ListView::new`, and bound to item data
+/// with the `update` function. When the list is scrolled, elements that fall out of view are recycled
+/// for the items that come into view.
+///
+/// Items can have different heights. Item heights are measured when they become visible, and the
+/// height of items that haven't been measured yet is estimated from the average of the measured heights.
+/// The list should be given a bounded height, as it only shows items up to its max height constraint.
+///
+/// Elements are created and recycled outside of layout, when the list is scrolled or after a layout
+/// that changed the size of the viewport. The element of an item that contains the focus is never recycled.
+pub struct ListView<T: 'static, V: Visual + 'static> {
+    element: Element,
+    weak_this: RefCell<Weak<ListView<T, V>>>,
+    selection_changed: Handler<Option<usize>>,
+    items: RefCell<Vec<T>>,
+    create: Box<dyn Fn() -> Rc<V>>,
+    update: Box<dyn Fn(&V, usize, &T)>,
+    /// Measured heights of the items, `None` if not measured yet.
+    heights: RefCell<Vec<Option<f64>>>,
+    /// Estimated height of items, used until at least one item has been measured.
+    estimated_item_height: Cell<f64>,
+    /// Sum and count of the measured heights.
+    measured: Cell<(f64, usize)>,
+    /// Scroll position: index of the first visible item and the offset of the top of the viewport
+    /// relative to the top of this item.
+    anchor: Cell<(usize, f64)>,
+    realized: RefCell<Vec<RealizedItem<V>>>,
+    /// Elements that aren't displaying any item and can be reused.
+    recycled: RefCell<Vec<Rc<V>>>,
+    selected: Cell<Option<usize>>,
+    selection_color: Cell<Color>,
+    /// Height of the viewport and constraints passed to the items during the last layout.
+    viewport_height: Cell<f64>,
+    item_constraints: Cell<Option<BoxConstraints>>,
+    /// Whether the realized items will be updated after the current layout.
+    realize_scheduled: Cell<bool>,
+}
+
+impl<T: 'static, V: Visual + 'static> Deref for ListView<T, V> {
+    type Target = Element;
+
+    fn deref(&self) -> &Self::Target {
+        &self.element
+    }
+}
+
+impl<T: 'static, V: Visual + 'static> ListView<T, V> {
+    /// Creates a new list view.
+    ///
+    /// # Arguments
+    ///
+    /// * `create` creates an element to display items
+    /// * `update` updates an element (as returned by `create`) with the data of the item at the specified index
+    pub fn new(create: impl Fn() -> Rc<V> + 'static, update: impl Fn(&V, usize, &T) + 'static) -> Rc<ListView<T, V>> {
+        let list_view = Element::new_derived(|element| ListView {
+            element,
+            weak_this: RefCell::new(Weak::new()),
+            selection_changed: Handler::new(),
+            items: RefCell::new(Vec::new()),
+            create: Box::new(create),
+            update: Box::new(update),
+            heights: RefCell::new(Vec::new()),
+            estimated_item_height: Cell::new(24.0),
+            measured: Cell::new((0.0, 0)),
+            anchor: Cell::new((0, 0.0)),
+            realized: RefCell::new(Vec::new()),
+            recycled: RefCell::new(Vec::new()),
+            selected: Cell::new(None),
+            selection_color: Cell::new(DARK_THEME.accent_color.with_alpha(0.4)),
+            viewport_height: Cell::new(0.0),
+            item_constraints: Cell::new(None),
+            realize_scheduled: Cell::new(false),
+        });
+        list_view.weak_this.replace(Rc::downgrade(&list_view));
+        list_view.set_tab_focusable(true);
+        list_view
+    }
+
+    /// Replaces the items of the list.
+    ///
+    /// This resets the selection and the measured item heights, but keeps the scroll position.
+    pub fn set_items(&self, items: Vec<T>) {
+        self.heights.replace(vec![None; items.len()]);
+        self.items.replace(items);
+        self.measured.set((0.0, 0));
+        self.selected.set(None);
+        // all elements must be bound again
+        let realized = mem::take(&mut *self.realized.borrow_mut());
+        {
+            let mut recycled = self.recycled.borrow_mut();
+            for item in realized {
+                item.element.element().detach();
+                recycled.push(item.element);
+            }
+        }
+        self.update_realized();
+    }
+
+    /// Returns the number of items in the list.
+    pub fn len(&self) -> usize {
+        self.items.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.borrow().is_empty()
+    }
+
+    /// Sets the height of items that haven't been measured yet, until at least one item has been measured.
+    pub fn set_estimated_item_height(&self, height: f64) {
+        self.estimated_item_height.set(height);
+        self.update_realized();
+    }
+
+    pub fn set_selection_color(&self, color: Color) {
+        self.selection_color.set(color);
+        self.mark_needs_repaint();
+    }
+
+    /// Returns the index of the selected item.
+    pub fn selected_index(&self) -> Option<usize> {
+        self.selected.get()
+    }
+
+    /// Selects the item at the specified index.
+    pub fn set_selected_index(&self, index: Option<usize>) {
+        let index = index.filter(|&i| i < self.len());
+        if self.selected.replace(index) != index {
+            self.mark_needs_repaint();
+        }
+    }
+
+    /// Emitted when the selection changes as a result of user interaction.
+    pub async fn selection_changed(&self) -> Option<usize> {
+        self.selection_changed.wait().await
+    }
+
+    /// Scrolls the list so that the item at the specified index is at the top.
+    pub fn scroll_to_index(&self, index: usize) {
+        self.anchor.set((index, 0.0));
+        self.update_realized();
+    }
+
+    /// Scrolls the list by the specified amount in pixels.
+    pub fn scroll_by(&self, delta: f64) {
+        let (index, offset) = self.anchor.get();
+        self.anchor.set((index, offset + delta));
+        self.update_realized();
+    }
+
+    /// Scrolls the list by the minimum amount so that the item at the specified index is fully visible.
+    pub fn ensure_visible(&self, index: usize) {
+        let viewport_height = self.geometry().size.height;
+        let (first, offset) = self.anchor.get();
+        if index < first || (index == first && offset > 0.0) {
+            self.scroll_to_index(index);
+            return;
+        }
+        let below = self
+            .realized
+            .borrow()
+            .iter()
+            .find(|item| item.index == index)
+            .map_or(true, |item| item.y + item.height > viewport_height);
+        if below {
+            // align the bottom of the item with the bottom of the viewport
+            self.anchor.set((index, self.item_height(index) - viewport_height));
+            self.update_realized();
+        }
+    }
+
+    /// Returns the estimated height of items that haven't been measured yet.
+    fn estimated_height(&self) -> f64 {
+        match self.measured.get() {
+            (total, count) if count > 0 => total / count as f64,
+            _ => self.estimated_item_height.get(),
+        }
+    }
+
+    /// Returns the measured or estimated height of an item.
+    fn item_height(&self, index: usize) -> f64 {
+        self.heights.borrow()[index].unwrap_or_else(|| self.estimated_height())
+    }
+
+    fn set_measured_height(&self, index: usize, height: f64) {
+        let prev = self.heights.borrow_mut()[index].replace(height);
+        let (total, count) = self.measured.get();
+        self.measured.set(match prev {
+            Some(prev) => (total - prev + height, count),
+            None => (total + height, count + 1),
+        });
+    }
+
+    /// Moves the scroll anchor to the first visible item.
+    fn normalize_anchor(&self) {
+        let count = self.len();
+        if count == 0 {
+            self.anchor.set((0, 0.0));
+            return;
+        }
+        let (mut index, mut offset) = self.anchor.get();
+        index = index.min(count - 1);
+        while offset < 0.0 && index > 0 {
+            index -= 1;
+            offset += self.item_height(index);
+        }
+        while offset > 0.0 && index + 1 < count && offset >= self.item_height(index) {
+            offset -= self.item_height(index);
+            index += 1;
+        }
+        self.anchor.set((index, offset.max(0.0)));
+    }
+
+    /// Creates elements for the items in the viewport, and recycles the elements of the items that went out of view.
+    ///
+    /// This adds and removes children, so it must not be called during layout. Items are measured with the
+    /// constraints of the last layout, so that the following layout finds the elements it needs.
+    fn update_realized(&self) {
+        let count = self.len();
+        let viewport_height = self.viewport_height.get();
+        let item_constraints = self.item_constraints.get();
+        let mut previous = mem::take(&mut *self.realized.borrow_mut());
+        let mut realized = Vec::new();
+        self.normalize_anchor();
+
+        // Realize items from the scroll anchor until the viewport is filled. If we reach the end
+        // of the list before that, scroll back and try again.
+        for pass in 0..2 {
+            let (anchor_index, anchor_offset) = self.anchor.get();
+            let mut index = anchor_index;
+            let mut y = -anchor_offset;
+            while index < count && y < viewport_height {
+                let element = self.realize(index, &mut previous);
+                let height = if let Some(item_constraints) = item_constraints {
+                    let visual: &dyn Visual = &*element;
+                    let height = visual.do_layout(&item_constraints).size.height;
+                    self.set_measured_height(index, height);
+                    height
+                } else {
+                    self.item_height(index)
+                };
+                realized.push(RealizedItem { index, element, y, height });
+                y += height;
+                index += 1;
+            }
+
+            let gap = viewport_height - y;
+            if pass == 0 && index == count && gap > 0.0 && (anchor_index > 0 || anchor_offset > 0.0) {
+                self.anchor.set((anchor_index, anchor_offset - gap));
+                self.normalize_anchor();
+                previous.append(&mut realized);
+                continue;
+            }
+            break;
+        }
+
+        // recycle the elements of items that went out of view
+        {
+            let mut recycled = self.recycled.borrow_mut();
+            for item in previous {
+                if item.element.element().has_focus_within() {
+                    // detaching the element would take the focus away from it, keep it out of view instead
+                    realized.push(item);
+                } else {
+                    item.element.element().detach();
+                    recycled.push(item.element);
+                }
+            }
+        }
+        realized.sort_by_key(|item| item.index);
+        self.realized.replace(realized);
+        self.mark_needs_relayout();
+    }
+
+    /// Updates the realized items once the current layout is done.
+    fn schedule_update_realized(&self) {
+        if self.realize_scheduled.replace(true) {
+            return;
+        }
+        let this_weak = self.weak_this.borrow().clone();
+        spawn(async move {
+            if let Some(this) = this_weak.upgrade() {
+                this.realize_scheduled.set(false);
+                this.update_realized();
+            }
+        });
+    }
+
+    /// Returns an element displaying the item at the specified index, reusing the element that already
+    /// displays it, or recycling or creating one.
+    fn realize(&self, index: usize, previous: &mut Vec<RealizedItem<V>>) -> Rc<V> {
+        if let Some(pos) = previous.iter().position(|item| item.index == index) {
+            return previous.swap_remove(pos).element;
+        }
+        let element = self.recycled.borrow_mut().pop().unwrap_or_else(|| (self.create)());
+        (self.update)(&*element, index, &self.items.borrow()[index]);
+        self.add_child(element.element());
+        element
+    }
+
+    /// Returns the index of the item at the specified position, in local coordinates.
+    fn item_at(&self, point: Point) -> Option<usize> {
+        self.realized
+            .borrow()
+            .iter()
+            .find(|item| point.y >= item.y && point.y < item.y + item.height)
+            .map(|item| item.index)
+    }
+
+    async fn select(&self, index: usize) {
+        if self.selected.get() != Some(index) {
+            self.set_selected_index(Some(index));
+            self.ensure_visible(index);
+            self.selection_changed.emit(Some(index)).await;
+        }
+    }
+}
+
+impl<T: 'static, V: Visual + 'static> Visual for ListView<T, V> {
+    fn element(&self) -> &Element {
+        &self.element
+    }
+
+    fn intrinsic_sizes(&self) -> IntrinsicSizes {
+        // only the realized items contribute to the width
+        let mut width = IntrinsicSizes::default();
+        for item in self.realized.borrow().iter() {
+            let s = item.element.intrinsic_sizes();
+            width.min.width = width.min.width.max(s.min.width);
+            width.max.width = width.max.width.max(s.max.width);
+        }
+        let height = self.intrinsic_height(width.max.width);
+        IntrinsicSizes {
+            min: Size::new(width.min.width, 0.0),
+            max: Size::new(width.max.width, height),
+        }
+    }
+
+    fn intrinsic_height(&self, _width: f64) -> f64 {
+        (0..self.len()).map(|i| self.item_height(i)).sum()
+    }
+
+    fn layout(&self, _children: &[Rc<dyn Visual>], constraints: &BoxConstraints) -> Geometry {
+        let count = self.len();
+        let item_constraints = BoxConstraints {
+            min: Size::new(constraints.finite_max_width().unwrap_or(0.0), 0.0),
+            max: Size::new(constraints.max.width, f64::INFINITY),
+        };
+        let viewport_height = if constraints.max.height.is_finite() {
+            constraints.max.height
+        } else {
+            constraints.min.height
+        };
+        let viewport_changed = self.viewport_height.replace(viewport_height) != viewport_height
+            || self.item_constraints.replace(Some(item_constraints)) != Some(item_constraints);
+
+        // Lay out the realized items from the scroll anchor. Items that are out of view (i.e. that contain
+        // the focus) are placed just outside of the viewport.
+        let (anchor_index, anchor_offset) = self.anchor.get();
+        let mut next_index = anchor_index;
+        let mut y = -anchor_offset;
+        let mut needs_update = viewport_changed;
+        let mut content_width: f64 = 0.0;
+        for item in self.realized.borrow_mut().iter_mut() {
+            let visual: &dyn Visual = &*item.element;
+            item.height = visual.do_layout(&item_constraints).size.height;
+            self.set_measured_height(item.index, item.height);
+            content_width = content_width.max(visual.geometry().size.width);
+            if item.index == next_index && y < viewport_height {
+                item.y = y;
+                y += item.height;
+                next_index += 1;
+            } else {
+                item.y = if item.index < anchor_index { -item.height } else { y.max(viewport_height) };
+                needs_update |= !item.element.element().has_focus_within();
+            }
+            visual.set_offset(Vec2::new(0.0, item.y));
+        }
+
+        // The measured heights may differ from the estimates used to realize the items: if the viewport isn't
+        // filled, realize the missing items (or scroll back if the end of the list was reached).
+        let can_scroll_back = anchor_index > 0 || anchor_offset > 0.0;
+        if y < viewport_height && (next_index < count || can_scroll_back) {
+            needs_update = true;
+        }
+        if needs_update {
+            self.schedule_update_realized();
+        }
+
+        let width = constraints.finite_max_width().unwrap_or(content_width);
+        let size = constraints.constrain(Size::new(width, viewport_height));
+        self.set_clip_rect(Some(size.to_rect()));
+        Geometry::new(size)
+    }
+
+    fn hit_test(&self, point: Point) -> bool {
+        self.geometry().size.to_rect().contains(point)
+    }
+
+    fn paint(&self, ctx: &mut PaintCtx) {
+        let Some(selected) = self.selected.get() else { return };
+        let realized = self.realized.borrow();
+        let Some(item) = realized.iter().find(|item| item.index == selected) else { return };
+        let size = self.geometry().size;
+        let rect = Rect::new(0.0, item.y, size.width, item.y + item.height).intersect(size.to_rect());
+        ctx.with_canvas(|canvas| {
+            let paint = sk::Paint::new(self.selection_color.get().to_skia(), None);
+            canvas.draw_rect(rect.to_skia(), &paint);
+        });
+    }
+
+    async fn event(&self, ctx: &mut EventCtx, event: &mut Event)
+    where
+        Self: Sized,
+    {
+        match event {
+            Event::Wheel(we) => {
+                self.scroll_by(we.pixel_delta().y);
+                ctx.set_handled();
+            }
+            Event::PointerDown(pe) => {
+                if let Some(index) = self.item_at(pe.local_position()) {
+                    self.select(index).await;
+                }
+                self.set_focus().await;
+            }
+            Event::KeyDown(ke) => {
+                let count = self.len();
+                if count == 0 {
+                    return;
+                }
+                let selected = self.selected.get();
+                let index = match ke.key {
+                    Key::ArrowDown => selected.map_or(0, |i| (i + 1).min(count - 1)),
+                    Key::ArrowUp => selected.map_or(0, |i| i.saturating_sub(1)),
+                    Key::Home => 0,
+                    Key::End => count - 1,
+                    _ => return,
+                };
+                self.select(index).await;
+                ctx.set_handled();
+            }
+            _ => {}
+        }
+    }
+}
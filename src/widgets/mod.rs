@@ -4,4 +4,6 @@ pub mod button;
 pub mod decorated_box;
 mod interact;
 pub mod frame;
-pub mod text_edit;
\ No newline at end of file
+pub mod text_edit;
+pub mod scroll_view;
+pub mod list_view;
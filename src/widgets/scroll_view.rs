@@ -0,0 +1,551 @@
+//! Scrollable viewport.
+
+use std::cell::{Cell, RefCell};
+use std::ops::Deref;
+use std::rc::Rc;
+
+use kurbo::{Point, Rect, Size, Vec2};
+use skia_safe as sk;
+
+use crate::drawing::ToSkia;
+use crate::element::{AttachedProperty, Element, Visual, ZIndex};
+use crate::event::{Event, EventCtx, Modifiers};
+use crate::handler::Handler;
+use crate::layout::flex::Axis;
+use crate::layout::{BoxConstraints, Geometry, IntrinsicSizes};
+use crate::theme::DARK_THEME;
+use crate::{Color, PaintCtx};
+
+/// Appearance of the scroll bars of a `ScrollView`.
+#[derive(Clone, Debug)]
+pub struct ScrollBarStyle {
+    /// Width of the vertical scroll bar, and height of the horizontal scroll bar.
+    pub thickness: f64,
+    /// Minimum length of the thumb.
+    pub min_thumb_length: f64,
+    /// Corner radius of the thumb.
+    pub thumb_radius: f64,
+    pub track_color: Color,
+    pub thumb_color: Color,
+    /// Color of the thumb when hovered or dragged.
+    pub thumb_active_color: Color,
+}
+
+impl Default for ScrollBarStyle {
+    fn default() -> Self {
+        ScrollBarStyle {
+            thickness: 10.0,
+            min_thumb_length: 20.0,
+            thumb_radius: 4.0,
+            track_color: Color::from_rgba_u8(0, 0, 0, 0),
+            thumb_color: DARK_THEME.text_color.with_alpha(0.4),
+            thumb_active_color: DARK_THEME.text_color.with_alpha(0.7),
+        }
+    }
+}
+
+/// A scroll bar, painted over the content of a `ScrollView`.
+///
+/// Pointer events on scroll bars are handled by the parent scroll view.
+struct ScrollBar {
+    element: Element,
+    style: RefCell<ScrollBarStyle>,
+    /// Thumb rectangle in the local coordinates of the scroll bar.
+    thumb: Cell<Rect>,
+    /// Whether the thumb is hovered or dragged.
+    active: Cell<bool>,
+}
+
+impl Deref for ScrollBar {
+    type Target = Element;
+
+    fn deref(&self) -> &Self::Target {
+        &self.element
+    }
+}
+
+impl ScrollBar {
+    fn new() -> Rc<ScrollBar> {
+        let bar = Element::new_derived(|element| ScrollBar {
+            element,
+            style: RefCell::new(ScrollBarStyle::default()),
+            thumb: Cell::new(Rect::ZERO),
+            active: Cell::new(false),
+        });
+        // paint scroll bars on top of the content
+        ZIndex.set(&*bar, i32::MAX);
+        bar
+    }
+
+    fn set_thumb(&self, thumb: Rect) {
+        if self.thumb.get() != thumb {
+            self.thumb.set(thumb);
+            self.mark_needs_repaint();
+        }
+    }
+
+    fn set_active(&self, active: bool) {
+        if self.active.get() != active {
+            self.active.set(active);
+            self.mark_needs_repaint();
+        }
+    }
+
+    /// Returns whether the scroll bar is shown.
+    fn is_visible(&self) -> bool {
+        let size = self.geometry().size;
+        size.width > 0.0 && size.height > 0.0
+    }
+}
+
+impl Visual for ScrollBar {
+    fn element(&self) -> &Element {
+        &self.element
+    }
+
+    fn intrinsic_sizes(&self) -> IntrinsicSizes {
+        IntrinsicSizes::default()
+    }
+
+    fn intrinsic_height(&self, _width: f64) -> f64 {
+        0.0
+    }
+
+    fn layout(&self, _children: &[Rc<dyn Visual>], constraints: &BoxConstraints) -> Geometry {
+        // sized by the parent scroll view
+        Geometry::new(constraints.min)
+    }
+
+    fn hit_test(&self, point: Point) -> bool {
+        self.geometry().size.to_rect().contains(point)
+    }
+
+    fn paint(&self, ctx: &mut PaintCtx) {
+        if !self.is_visible() {
+            return;
+        }
+        let size = self.geometry().size;
+        let style = self.style.borrow();
+        let thumb_color = if self.active.get() {
+            style.thumb_active_color
+        } else {
+            style.thumb_color
+        };
+        ctx.with_canvas(|canvas| {
+            let mut paint = sk::Paint::new(style.track_color.to_skia(), None);
+            canvas.draw_rect(size.to_rect().to_skia(), &paint);
+            paint.set_color4f(thumb_color.to_skia(), None);
+            paint.set_anti_alias(true);
+            let thumb = self.thumb.get().inset(-2.0).to_rounded_rect(style.thumb_radius);
+            canvas.draw_rrect(thumb.to_skia(), &paint);
+        });
+    }
+
+    async fn event(&self, _ctx: &mut EventCtx, _event: &mut Event)
+    where
+        Self: Sized,
+    {
+    }
+}
+
+/// State of a scroll bar thumb drag.
+#[derive(Copy, Clone)]
+struct ThumbDrag {
+    axis: Axis,
+    /// Pointer position along the axis when the drag started.
+    start_position: f64,
+    /// Scroll offset along the axis when the drag started.
+    start_offset: f64,
+}
+
+/// A viewport over content that can be larger than the available space.
+///
+/// Children are laid out with unbounded constraints on the scrollable axes, and clipped to the bounds
+/// of the scroll view. The content can be scrolled with the mouse wheel, or by dragging the scroll bars.
+pub struct ScrollView {
+    element: Element,
+    /// Emitted when the scroll offset changes.
+    pub scrolled: Handler<Vec2>,
+    horizontal: Cell<bool>,
+    vertical: Cell<bool>,
+    offset: Cell<Vec2>,
+    content_size: Cell<Size>,
+    /// Size of the visible area, as of the last layout.
+    viewport_size: Cell<Size>,
+    horizontal_bar: Rc<ScrollBar>,
+    vertical_bar: Rc<ScrollBar>,
+    drag: Cell<Option<ThumbDrag>>,
+}
+
+impl Deref for ScrollView {
+    type Target = Element;
+
+    fn deref(&self) -> &Self::Target {
+        &self.element
+    }
+}
+
+impl ScrollView {
+    /// Creates a new `ScrollView` that scrolls vertically.
+    pub fn new() -> Rc<ScrollView> {
+        let scroll_view = Element::new_derived(|element| ScrollView {
+            element,
+            scrolled: Default::default(),
+            horizontal: Cell::new(false),
+            vertical: Cell::new(true),
+            offset: Cell::new(Vec2::ZERO),
+            content_size: Cell::new(Size::ZERO),
+            viewport_size: Cell::new(Size::ZERO),
+            horizontal_bar: ScrollBar::new(),
+            vertical_bar: ScrollBar::new(),
+            drag: Cell::new(None),
+        });
+        scroll_view.add_child(&scroll_view.horizontal_bar);
+        scroll_view.add_child(&scroll_view.vertical_bar);
+        scroll_view
+    }
+
+    /// Sets the axes along which the content can be scrolled.
+    pub fn set_scroll_axes(&self, horizontal: bool, vertical: bool) {
+        self.horizontal.set(horizontal);
+        self.vertical.set(vertical);
+        self.mark_needs_relayout();
+    }
+
+    /// Sets the appearance of the scroll bars.
+    pub fn set_scroll_bar_style(&self, style: ScrollBarStyle) {
+        self.horizontal_bar.style.replace(style.clone());
+        self.vertical_bar.style.replace(style);
+        self.mark_needs_relayout();
+    }
+
+    /// Returns the current scroll offset.
+    pub fn scroll_offset(&self) -> Vec2 {
+        self.offset.get()
+    }
+
+    /// Returns the size of the content.
+    pub fn content_size(&self) -> Size {
+        self.content_size.get()
+    }
+
+    /// Returns the maximum scroll offset.
+    pub fn max_scroll_offset(&self) -> Vec2 {
+        let content = self.content_size.get();
+        let viewport = self.viewport_size.get();
+        Vec2::new(
+            if self.horizontal.get() { (content.width - viewport.width).max(0.0) } else { 0.0 },
+            if self.vertical.get() { (content.height - viewport.height).max(0.0) } else { 0.0 },
+        )
+    }
+
+    /// Scrolls the content to the specified offset, clamped to the scrollable range.
+    ///
+    /// Returns whether the offset has changed.
+    pub fn set_scroll_offset(&self, offset: Vec2) -> bool {
+        let max = self.max_scroll_offset();
+        let offset = Vec2::new(offset.x.clamp(0.0, max.x), offset.y.clamp(0.0, max.y));
+        if offset == self.offset.get() {
+            return false;
+        }
+        self.offset.set(offset);
+        // scrolling only moves the content, no need to relayout
+        for child in self.content_children() {
+            child.set_offset(-offset);
+        }
+        self.update_thumbs();
+        self.mark_needs_repaint();
+        true
+    }
+
+    /// Returns the children of the scroll view, minus the scroll bars.
+    fn content_children(&self) -> Vec<Rc<dyn Visual>> {
+        let mut children = self.children();
+        children.retain(|c| !self.is_scroll_bar(&**c));
+        children
+    }
+
+    fn is_scroll_bar(&self, visual: &dyn Visual) -> bool {
+        visual.is_same(&*self.horizontal_bar) || visual.is_same(&*self.vertical_bar)
+    }
+
+    fn scroll_bar(&self, axis: Axis) -> &ScrollBar {
+        match axis {
+            Axis::Horizontal => &self.horizontal_bar,
+            Axis::Vertical => &self.vertical_bar,
+        }
+    }
+
+    /// Returns the rectangle of the scroll bar along the specified axis, in the local coordinates of the scroll view.
+    fn scroll_bar_rect(&self, axis: Axis) -> Rect {
+        let bar = self.scroll_bar(axis);
+        bar.geometry().size.to_rect() + bar.transform().translation()
+    }
+
+    /// Returns the length of the thumb and the length of the track of the scroll bar along the specified axis.
+    fn thumb_and_track_lengths(&self, axis: Axis) -> (f64, f64) {
+        let bar = self.scroll_bar(axis);
+        let viewport = self.viewport_size.get();
+        let content = self.content_size.get();
+        let (track, visible, total) = match axis {
+            Axis::Horizontal => (bar.geometry().size.width, viewport.width, content.width),
+            Axis::Vertical => (bar.geometry().size.height, viewport.height, content.height),
+        };
+        let min_thumb = bar.style.borrow().min_thumb_length;
+        let thumb = if total > 0.0 { track * visible / total } else { track };
+        (thumb.max(min_thumb).min(track), track)
+    }
+
+    /// Updates the position of the scroll bar thumbs from the scroll offset.
+    fn update_thumbs(&self) {
+        let offset = self.offset.get();
+        let max = self.max_scroll_offset();
+        for axis in [Axis::Horizontal, Axis::Vertical] {
+            let bar = self.scroll_bar(axis);
+            let size = bar.geometry().size;
+            let (thumb, track) = self.thumb_and_track_lengths(axis);
+            let (offset, max) = match axis {
+                Axis::Horizontal => (offset.x, max.x),
+                Axis::Vertical => (offset.y, max.y),
+            };
+            let pos = if max > 0.0 { (track - thumb) * offset / max } else { 0.0 };
+            bar.set_thumb(match axis {
+                Axis::Horizontal => Rect::new(pos, 0.0, pos + thumb, size.height),
+                Axis::Vertical => Rect::new(0.0, pos, size.width, pos + thumb),
+            });
+        }
+    }
+
+    /// Returns the scroll bar whose thumb is under the specified point, in local coordinates.
+    fn thumb_at(&self, point: Point) -> Option<Axis> {
+        [Axis::Horizontal, Axis::Vertical].into_iter().find(|&axis| {
+            let bar = self.scroll_bar(axis);
+            bar.is_visible() && bar.thumb.get().contains(point - bar.transform().translation())
+        })
+    }
+
+    fn update_hover(&self, point: Option<Point>) {
+        let hovered = point.and_then(|p| self.thumb_at(p));
+        let dragged = self.drag.get().map(|d| d.axis);
+        for axis in [Axis::Horizontal, Axis::Vertical] {
+            self.scroll_bar(axis)
+                .set_active(hovered == Some(axis) || dragged == Some(axis));
+        }
+    }
+
+    /// Scrolls to the specified offset and emits `scrolled`. Returns whether the offset has changed.
+    async fn scroll_to(&self, offset: Vec2) -> bool {
+        if self.set_scroll_offset(offset) {
+            self.scrolled.emit(self.offset.get()).await;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Visual for ScrollView {
+    fn element(&self) -> &Element {
+        &self.element
+    }
+
+    fn intrinsic_sizes(&self) -> IntrinsicSizes {
+        let mut isizes = IntrinsicSizes::default();
+        for child in self.content_children() {
+            let s = child.intrinsic_sizes();
+            isizes.min.width = isizes.min.width.max(s.min.width);
+            isizes.min.height = isizes.min.height.max(s.min.height);
+            isizes.max.width = isizes.max.width.max(s.max.width);
+            isizes.max.height = isizes.max.height.max(s.max.height);
+        }
+        // the viewport can shrink down to nothing along the scrollable axes
+        if self.horizontal.get() {
+            isizes.min.width = 0.0;
+        }
+        if self.vertical.get() {
+            isizes.min.height = 0.0;
+        }
+        isizes
+    }
+
+    fn intrinsic_height(&self, width: f64) -> f64 {
+        self.content_children()
+            .iter()
+            .map(|child| child.intrinsic_height(width))
+            .fold(0.0, f64::max)
+    }
+
+    fn layout(&self, _children: &[Rc<dyn Visual>], constraints: &BoxConstraints) -> Geometry {
+        let horizontal = self.horizontal.get();
+        let vertical = self.vertical.get();
+
+        // lay out the content with unbounded constraints on the scrollable axes
+        let content_constraints = BoxConstraints {
+            min: Size::ZERO,
+            max: Size::new(
+                if horizontal { f64::INFINITY } else { constraints.max.width },
+                if vertical { f64::INFINITY } else { constraints.max.height },
+            ),
+        };
+        let mut content_size = Size::ZERO;
+        let content_children = self.content_children();
+        for child in content_children.iter() {
+            let geometry = child.do_layout(&content_constraints);
+            content_size.width = content_size.width.max(geometry.size.width);
+            content_size.height = content_size.height.max(geometry.size.height);
+        }
+        self.content_size.set(content_size);
+
+        let size = constraints.constrain(content_size);
+        self.viewport_size.set(size);
+        self.set_clip_rect(Some(size.to_rect()));
+
+        // scroll bars are painted over the content, along the right and bottom edges
+        let thickness = self.vertical_bar.style.borrow().thickness;
+        let show_horizontal = horizontal && content_size.width > size.width;
+        let show_vertical = vertical && content_size.height > size.height;
+        let corner = if show_horizontal && show_vertical { thickness } else { 0.0 };
+        let horizontal_bar_size = if show_horizontal {
+            Size::new((size.width - corner).max(0.0), thickness)
+        } else {
+            Size::ZERO
+        };
+        let vertical_bar_size = if show_vertical {
+            Size::new(thickness, (size.height - corner).max(0.0))
+        } else {
+            Size::ZERO
+        };
+        let horizontal_bar: &dyn Visual = &*self.horizontal_bar;
+        horizontal_bar.do_layout(&BoxConstraints::tight(horizontal_bar_size));
+        horizontal_bar.set_offset(Vec2::new(0.0, size.height - horizontal_bar_size.height));
+        let vertical_bar: &dyn Visual = &*self.vertical_bar;
+        vertical_bar.do_layout(&BoxConstraints::tight(vertical_bar_size));
+        vertical_bar.set_offset(Vec2::new(size.width - vertical_bar_size.width, 0.0));
+
+        // clamp the offset to the new scrollable range
+        let max = self.max_scroll_offset();
+        let offset = self.offset.get();
+        let offset = Vec2::new(offset.x.clamp(0.0, max.x), offset.y.clamp(0.0, max.y));
+        self.offset.set(offset);
+        for child in content_children.iter() {
+            child.set_offset(-offset);
+        }
+
+        self.update_thumbs();
+        Geometry::new(size)
+    }
+
+    fn hit_test(&self, point: Point) -> bool {
+        self.geometry().size.to_rect().contains(point)
+    }
+
+    fn scroll_rect_into_view(&self, rect: Rect) -> Rect {
+        // Returns by how much to scroll along an axis to show [min, max] in [0, size].
+        // If the range doesn't fit, its start is shown.
+        fn reveal(min: f64, max: f64, size: f64) -> f64 {
+            if min < 0.0 {
+                min
+            } else if max > size {
+                (max - size).min(min)
+            } else {
+                0.0
+            }
+        }
+
+        let viewport = self.viewport_size.get();
+        let offset = self.offset.get();
+        let delta = Vec2::new(
+            if self.horizontal.get() { reveal(rect.x0, rect.x1, viewport.width) } else { 0.0 },
+            if self.vertical.get() { reveal(rect.y0, rect.y1, viewport.height) } else { 0.0 },
+        );
+        self.set_scroll_offset(offset + delta);
+        rect - (self.offset.get() - offset)
+    }
+
+    fn paint(&self, _ctx: &mut PaintCtx) {}
+
+    async fn event(&self, ctx: &mut EventCtx, event: &mut Event)
+    where
+        Self: Sized,
+    {
+        match event {
+            Event::Wheel(we) => {
+                let mut delta = we.pixel_delta();
+                // shift+wheel scrolls horizontally, as does the vertical wheel on horizontal-only scroll views
+                if we.pointer.modifiers.contains(Modifiers::SHIFT) || !self.vertical.get() {
+                    if delta.x == 0.0 {
+                        delta = Vec2::new(delta.y, 0.0);
+                    }
+                }
+                // let enclosing scroll views scroll when we can't scroll further
+                if self.scroll_to(self.offset.get() + delta).await {
+                    ctx.set_handled();
+                }
+            }
+            Event::PointerDown(pe) => {
+                let pos = pe.local_position();
+                if let Some(axis) = self.thumb_at(pos) {
+                    let offset = self.offset.get();
+                    self.drag.set(Some(ThumbDrag {
+                        axis,
+                        start_position: if axis == Axis::Horizontal { pos.x } else { pos.y },
+                        start_offset: if axis == Axis::Horizontal { offset.x } else { offset.y },
+                    }));
+                    self.set_pointer_capture();
+                    self.update_hover(Some(pos));
+                    ctx.set_handled();
+                    return;
+                }
+                // clicking on the track scrolls by one page towards the pointer
+                for axis in [Axis::Horizontal, Axis::Vertical] {
+                    let bar = self.scroll_bar(axis);
+                    if !bar.is_visible() || !self.scroll_bar_rect(axis).contains(pos) {
+                        continue;
+                    }
+                    let thumb = bar.thumb.get() + bar.transform().translation();
+                    let viewport = self.viewport_size.get();
+                    let offset = self.offset.get();
+                    let new_offset = match axis {
+                        Axis::Horizontal if pos.x < thumb.x0 => offset - Vec2::new(viewport.width, 0.0),
+                        Axis::Horizontal => offset + Vec2::new(viewport.width, 0.0),
+                        Axis::Vertical if pos.y < thumb.y0 => offset - Vec2::new(0.0, viewport.height),
+                        Axis::Vertical => offset + Vec2::new(0.0, viewport.height),
+                    };
+                    self.scroll_to(new_offset).await;
+                    ctx.set_handled();
+                    break;
+                }
+            }
+            Event::PointerMove(pe) => {
+                let pos = pe.local_position();
+                if let Some(drag) = self.drag.get() {
+                    let (thumb, track) = self.thumb_and_track_lengths(drag.axis);
+                    let max = self.max_scroll_offset();
+                    let offset = self.offset.get();
+                    let new_offset = match drag.axis {
+                        Axis::Horizontal if track > thumb => Vec2::new(
+                            drag.start_offset + (pos.x - drag.start_position) * max.x / (track - thumb),
+                            offset.y,
+                        ),
+                        Axis::Vertical if track > thumb => Vec2::new(
+                            offset.x,
+                            drag.start_offset + (pos.y - drag.start_position) * max.y / (track - thumb),
+                        ),
+                        _ => offset,
+                    };
+                    self.scroll_to(new_offset).await;
+                }
+                self.update_hover(Some(pos));
+            }
+            Event::PointerUp(pe) => {
+                if self.drag.take().is_some() {
+                    self.update_hover(Some(pe.local_position()));
+                }
+            }
+            Event::PointerLeave(_) => {
+                self.update_hover(None);
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,6 +1,6 @@
 use crate::drawing::ToSkia;
 use crate::element::{AnyVisual, Element, Visual};
-use crate::event::Event;
+use crate::event::{Event, EventCtx};
 use crate::layout::{BoxConstraints, Geometry, IntrinsicSizes};
 use crate::PaintCtx;
 use kurbo::{Point, Size};
@@ -11,10 +11,39 @@ use std::rc::Rc;
 use tracy_client::span;
 use crate::text::{AttributedStr, FormattedText};
 
+/// Returns the min-content and max-content sizes of a paragraph.
+///
+/// This lays out the paragraph, so it must be laid out again before painting.
+pub(crate) fn paragraph_intrinsic_sizes(paragraph: &mut textlayout::Paragraph) -> IntrinsicSizes {
+    // intrinsic widths are computed during layout
+    paragraph.layout(f32::INFINITY);
+    // round up so that laying out the paragraph with these widths doesn't introduce line breaks
+    let min_width = paragraph.min_intrinsic_width().ceil();
+    let max_width = paragraph.max_intrinsic_width().ceil();
+    paragraph.layout(min_width);
+    let min_height = paragraph.height();
+    paragraph.layout(max_width);
+    let max_height = paragraph.height();
+    IntrinsicSizes {
+        min: Size::new(min_width as f64, min_height as f64),
+        max: Size::new(max_width as f64, max_height as f64),
+    }
+}
+
+/// Returns the height of a paragraph laid out with the specified width.
+///
+/// This lays out the paragraph, so it must be laid out again before painting.
+pub(crate) fn paragraph_height_for_width(paragraph: &mut textlayout::Paragraph, width: f64) -> f64 {
+    paragraph.layout(width as f32);
+    paragraph.height() as f64
+}
+
 pub struct Text {
     element: Element,
     relayout: Cell<bool>,
-    intrinsic_size: Cell<Option<Size>>,
+    /// Width of the last paragraph layout.
+    layout_width: Cell<Option<f32>>,
+    intrinsic_sizes: Cell<Option<IntrinsicSizes>>,
     paragraph: RefCell<textlayout::Paragraph>,
 }
 
@@ -27,20 +56,27 @@ impl Deref for Text {
 }
 
 impl Text {
+    /// Lays out the paragraph again with the width of the last layout after it has been measured.
+    ///
+    /// `layout` may be skipped if the constraints didn't change, so the paragraph must be left as it was.
+    fn restore_paragraph_layout(&self, paragraph: &mut textlayout::Paragraph) {
+        if let Some(width) = self.layout_width.get() {
+            paragraph.layout(width);
+        } else {
+            self.relayout.set(true);
+        }
+    }
+
     pub fn new(text: &AttributedStr) -> Rc<Text> {
         let paragraph = FormattedText::from_attributed_str(text).inner;
         Element::new_derived(|element| Text {
             element,
             relayout: Cell::new(true),
-            intrinsic_size: Cell::new(None),
+            layout_width: Cell::new(None),
+            intrinsic_sizes: Cell::new(None),
             paragraph: RefCell::new(paragraph),
         })
     }
-
-    fn calculate_intrinsic_size(&self) -> Size {
-        // FIXME intrinsic height
-        Size::new(self.paragraph.borrow().max_intrinsic_width() as f64, 16.0)
-    }
 }
 
 
@@ -51,11 +87,21 @@ impl Visual for Text {
 
 
     fn intrinsic_sizes(&self) -> IntrinsicSizes {
-        let size = self.calculate_intrinsic_size();
-        IntrinsicSizes {
-            min: size,
-            max: size,
+        if let Some(sizes) = self.intrinsic_sizes.get() {
+            return sizes;
         }
+        let paragraph = &mut *self.paragraph.borrow_mut();
+        let sizes = paragraph_intrinsic_sizes(paragraph);
+        self.intrinsic_sizes.set(Some(sizes));
+        self.restore_paragraph_layout(paragraph);
+        sizes
+    }
+
+    fn intrinsic_height(&self, width: f64) -> f64 {
+        let paragraph = &mut *self.paragraph.borrow_mut();
+        let height = paragraph_height_for_width(paragraph, width);
+        self.restore_paragraph_layout(paragraph);
+        height
     }
 
     fn layout(&self, _children: &[Rc<dyn Visual>], constraints: &BoxConstraints) -> Geometry {
@@ -87,6 +133,7 @@ impl Visual for Text {
         }
 
         paragraph.layout(available_width as skia_safe::scalar);
+        self.layout_width.set(Some(available_width as skia_safe::scalar));
         let w = paragraph.longest_line() as f64;
         let h = paragraph.height() as f64;
         let alphabetic_baseline = paragraph.alphabetic_baseline();
@@ -112,7 +159,7 @@ impl Visual for Text {
         })
     }
 
-    async fn event(&self, event: &mut Event)
+    async fn event(&self, _ctx: &mut EventCtx, _event: &mut Event)
     where
         Self: Sized,
     {
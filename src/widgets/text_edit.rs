@@ -1,18 +1,22 @@
 use crate::application::{spawn, wait_for};
+use crate::clipboard;
+use crate::command::Command;
 use crate::drawing::{Paint, ToSkia};
 use crate::element::{AnyVisual, Element, Visual};
-use crate::event::Event;
+use crate::event::{Event, EventCtx, ImeEvent};
 use crate::handler::Handler;
-use crate::layout::{BoxConstraints, Geometry};
-use crate::text::{FormattedText, Selection, TextStyle};
+use crate::layout::{BoxConstraints, Geometry, IntrinsicSizes};
+use crate::text::{FormattedText, Selection, TextBuffer, TextChange, TextDirection, TextStyle};
+use crate::widgets::text::{paragraph_height_for_width, paragraph_intrinsic_sizes};
 use crate::{application, text, Color, PaintCtx};
 use futures_util::future::AbortHandle;
 use keyboard_types::Key;
 use kurbo::{Point, Rect, Size};
-use skia_safe::textlayout::{RectHeightStyle, RectWidthStyle};
+use skia_safe::textlayout::{GlyphClusterInfo, LineMetrics, TextDirection as SkTextDirection};
 use std::cell::{Cell, RefCell};
+use std::mem;
 use std::ops::{Deref, Range};
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tracing::warn;
@@ -31,11 +35,6 @@ fn prev_grapheme_cluster(text: &str, offset: usize) -> Option<usize> {
     c.prev_boundary(text, 0).unwrap()
 }
 
-fn next_grapheme_cluster(text: &str, offset: usize) -> Option<usize> {
-    let mut c = GraphemeCursor::new(offset, text.len(), true);
-    c.next_boundary(text, 0).unwrap()
-}
-
 /// If `other` comes before `self`, the cursor is placed at the beginning of the selection.
 fn add_selections(this: Selection, other: Selection) -> Selection {
     let min = this.min().min(other.min());
@@ -47,131 +46,412 @@ fn add_selections(this: Selection, other: Selection) -> Selection {
     }
 }
 
-struct TextEditState {
+/// Maximum time between two typed characters for them to be undone together.
+const UNDO_GROUP_TIMEOUT: Duration = Duration::from_secs(1);
+/// Maximum number of entries in the undo history.
+const UNDO_HISTORY_LEN: usize = 256;
+
+/// A change to the text, with the selection before and after it.
+#[derive(Clone, Debug, PartialEq)]
+struct Edit {
+    change: TextChange,
+    selection_before: Selection,
+    selection_after: Selection,
+}
+
+impl Edit {
+    /// Merges an edit that immediately follows this one, if they are contiguous.
+    ///
+    /// Returns `false` if they can't be merged.
+    fn merge(&mut self, next: &Edit) -> bool {
+        let (this, next_change) = (&mut self.change, &next.change);
+        if next_change.removed.is_empty() && next_change.offset == this.offset + this.inserted.len() {
+            // typing
+            this.inserted.push_str(&next_change.inserted);
+        } else if !next_change.inserted.is_empty() || !this.inserted.is_empty() {
+            return false;
+        } else if next_change.offset + next_change.removed.len() == this.offset {
+            // backspace
+            this.removed.insert_str(0, &next_change.removed);
+            this.offset = next_change.offset;
+        } else if next_change.offset == this.offset {
+            // delete
+            this.removed.push_str(&next_change.removed);
+        } else {
+            return false;
+        }
+        self.selection_after = next.selection_after;
+        true
+    }
+}
+
+/// Kinds of edits. Consecutive edits of the same kind are undone together.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum EditKind {
+    Typing,
+    DeleteBackward,
+    DeleteForward,
+    /// Edits that are never grouped (e.g. paste).
+    Other,
+}
+
+/// Undo/redo history of a text edit.
+#[derive(Default)]
+struct UndoStack {
+    undo: Vec<Edit>,
+    redo: Vec<Edit>,
+    /// Kind and time of the last edit, if the next edit may be merged with it.
+    open_group: Option<(EditKind, Instant)>,
+}
+
+impl UndoStack {
+    fn push(&mut self, edit: Edit, kind: EditKind, time: Instant) {
+        self.redo.clear();
+        if let Some((group_kind, last_time)) = self.open_group {
+            // typing a space after a word starts a new group, so that words are undone one at a time
+            let word_break = kind == EditKind::Typing
+                && edit.change.inserted.starts_with(char::is_whitespace)
+                && self.undo.last().is_some_and(|last| !last.change.inserted.ends_with(char::is_whitespace));
+            if group_kind == kind && time.duration_since(last_time) < UNDO_GROUP_TIMEOUT && !word_break {
+                if let Some(last) = self.undo.last_mut() {
+                    if last.merge(&edit) {
+                        self.open_group = Some((kind, time));
+                        return;
+                    }
+                }
+            }
+        }
+        self.undo.push(edit);
+        if self.undo.len() > UNDO_HISTORY_LEN {
+            self.undo.remove(0);
+        }
+        self.open_group = (kind != EditKind::Other).then_some((kind, time));
+    }
+
+    /// Prevents the next edit from being merged with the previous one.
+    fn close_group(&mut self) {
+        self.open_group = None;
+    }
+
+    fn undo(&mut self) -> Option<Edit> {
+        self.open_group = None;
+        let edit = self.undo.pop()?;
+        self.redo.push(edit.clone());
+        Some(edit)
+    }
+
+    fn redo(&mut self) -> Option<Edit> {
+        self.open_group = None;
+        let edit = self.redo.pop()?;
+        self.undo.push(edit.clone());
+        Some(edit)
+    }
+}
+
+/// Name of the undo command of text edits.
+pub const UNDO: &str = "edit.undo";
+/// Name of the redo command of text edits.
+pub const REDO: &str = "edit.redo";
+/// Name of the cut command of text edits.
+pub const CUT: &str = "edit.cut";
+/// Name of the copy command of text edits.
+pub const COPY: &str = "edit.copy";
+/// Name of the paste command of text edits.
+pub const PASTE: &str = "edit.paste";
+/// Name of the select all command of text edits.
+pub const SELECT_ALL: &str = "edit.select_all";
+
+/// Text being composed with the input method.
+struct Preedit {
     text: String,
+    /// Byte range of the composition cursor in `text`.
+    cursor: Option<(usize, usize)>,
+}
+
+/// Transforms text entered by the user before it is inserted.
+pub type InputFilter = Rc<dyn Fn(&str) -> String>;
+/// Returns whether the text resulting from an edit is acceptable.
+pub type TextValidator = Rc<dyn Fn(&str) -> bool>;
+
+/// Input filter that only keeps ASCII digits.
+pub fn digits_only(text: &str) -> String {
+    text.chars().filter(char::is_ascii_digit).collect()
+}
+
+struct TextEditState {
+    buffer: TextBuffer,
+    /// The text laid out in `paragraph`: the contents of the buffer with the IME composition in place of the selection.
+    display_text: String,
     selection: Selection,
+    /// IME composition in progress, displayed in place of the selection.
+    ///
+    /// The selection is only replaced when the composition is committed, so that canceling it leaves the text as is.
+    preedit: Option<Preedit>,
+    undo_stack: UndoStack,
+    /// Changes made by the user that haven't been reported by `text_changed` yet.
+    pending_changes: Vec<TextChange>,
+    input_filter: Option<InputFilter>,
+    validator: Option<TextValidator>,
+    /// Maximum number of chars in the text.
+    max_length: Option<usize>,
+    read_only: bool,
+    single_line: bool,
+    /// Horizontal position that the caret tries to keep when moving vertically across lines of different lengths.
+    preferred_x: Option<f64>,
+    /// Whether to scroll the caret into view after the next layout.
+    reveal_caret: bool,
+    base_direction: TextDirection,
+    /// Position of the caret after the last horizontal movement in visual order.
+    ///
+    /// At direction boundaries, the caret offset has two positions on screen: this tells which one the caret
+    /// was moved to.
+    caret_x: Option<f64>,
     text_style: TextStyle<'static>,
     last_available_width: f64,
-    paragraph: skia_safe::textlayout::Paragraph,
+    paragraph: FormattedText,
     selection_color: Color,
     caret_color: Color,
     relayout: bool,
 }
 
 impl TextEditState {
-    fn rebuild_paragraph(&mut self) {
-        let text = self.text.clone();
-        self.paragraph = FormattedText::new(text!( style(self.text_style) "{text}")).inner;
+    /// Returns the position of the caret in the displayed text.
+    fn caret_offset(&self) -> usize {
+        match self.preedit {
+            Some(ref preedit) => self.selection.min() + preedit.cursor.map_or(preedit.text.len(), |(_, end)| end),
+            None => self.selection.end,
+        }
     }
-}
 
-const CARET_BLINK_INITIAL_DELAY: Duration = Duration::from_secs(1);
-const CARET_BLINK_INTERVAL: Duration = Duration::from_millis(500);
-
-fn next_word_boundary(text: &str, offset: usize) -> usize {
-    let mut pos = offset;
-    enum State {
-        LeadingWhitespace,
-        Alnum,
-        NotAlnum,
-    }
-    let mut state = State::LeadingWhitespace;
-    for ch in text[offset..].chars() {
-        match state {
-            State::LeadingWhitespace => {
-                if !ch.is_whitespace() {
-                    if ch.is_alphanumeric() {
-                        state = State::Alnum;
-                    } else {
-                        state = State::NotAlnum;
-                    }
-                }
+    fn rebuild_paragraph(&mut self) {
+        // copy the rope chunks directly, with the composition in place of the selection
+        self.display_text.clear();
+        match self.preedit {
+            Some(ref preedit) => {
+                let selection = self.selection.byte_range();
+                self.buffer.push_slice_to(0..selection.start, &mut self.display_text);
+                self.display_text.push_str(&preedit.text);
+                self.buffer.push_slice_to(selection.end..self.buffer.len(), &mut self.display_text);
             }
-            State::Alnum => {
-                if !ch.is_alphanumeric() {
-                    return pos;
+            None => self.buffer.push_slice_to(0..self.buffer.len(), &mut self.display_text),
+        }
+        let text = &self.display_text;
+        self.paragraph =
+            FormattedText::with_base_direction(text!( style(self.text_style) "{text}"), self.base_direction);
+    }
+
+    /// Adjusts text entered by the user to the constraints of the text edit.
+    ///
+    /// Returns `None` if the text can't be inserted in place of `range`.
+    fn sanitize_input(&self, range: Range<usize>, text: &str) -> Option<String> {
+        if self.read_only {
+            return None;
+        }
+        let mut text = if self.single_line {
+            text.replace("\r\n", " ").replace(['\n', '\r'], " ")
+        } else {
+            text.to_string()
+        };
+        if let Some(ref filter) = self.input_filter {
+            text = filter(&text);
+        }
+        if let Some(max_length) = self.max_length {
+            let removed = self.buffer.slice(range).chars().count();
+            let available = max_length.saturating_sub(self.buffer.char_count() - removed);
+            // truncate on a grapheme cluster boundary
+            let mut len = 0;
+            let mut char_count = 0;
+            for grapheme in text.graphemes(true) {
+                char_count += grapheme.chars().count();
+                if char_count > available {
+                    break;
                 }
+                len += grapheme.len();
             }
-            State::NotAlnum => {
-                return pos;
-            }
+            text.truncate(len);
         }
-        pos += ch.len_utf8();
+        Some(text)
+    }
+
+    /// Returns whether the text resulting from a change is accepted by the validator.
+    fn validate(&self, change: &TextChange) -> bool {
+        let Some(ref validator) = self.validator else { return true };
+        let mut text = String::with_capacity(self.buffer.len() + change.inserted.len() - change.removed.len());
+        self.buffer.push_slice_to(0..change.offset, &mut text);
+        text.push_str(&change.inserted);
+        self.buffer.push_slice_to(change.offset + change.removed.len()..self.buffer.len(), &mut text);
+        validator(&text)
     }
-    pos
 }
 
-fn prev_word_boundary(text: &str, offset: usize) -> usize {
-    let mut pos = offset;
-    enum State {
-        LeadingWhitespace,
-        Alnum,
-        NotAlnum,
-    }
-    let mut state = State::LeadingWhitespace;
-    for ch in text[..offset].chars().rev() {
-        match state {
-            State::LeadingWhitespace => {
-                if !ch.is_whitespace() {
-                    if ch.is_alphanumeric() {
-                        state = State::Alnum;
-                    } else {
-                        state = State::NotAlnum;
-                    }
-                }
-            }
-            State::Alnum => {
-                if !ch.is_alphanumeric() {
-                    return pos;
-                }
+/// Returns the x coordinate of the leading or trailing edge of a glyph cluster, which depends on its direction.
+fn cluster_edge(info: &GlyphClusterInfo, leading: bool) -> f64 {
+    if (info.position == SkTextDirection::LTR) == leading {
+        info.bounds.left as f64
+    } else {
+        info.bounds.right as f64
+    }
+}
+
+/// Returns the x coordinate of the caret on an empty line.
+fn empty_line_x(state: &TextEditState) -> f64 {
+    if !state.base_direction.is_rtl() {
+        return 0.0;
+    }
+    // right-to-left lines start on the right
+    let width = state.paragraph.inner.max_width() as f64;
+    let width = if width.is_finite() {
+        width
+    } else {
+        state.paragraph.inner.longest_line() as f64
+    };
+    (width - 1.0).max(0.0)
+}
+
+/// Returns the rectangles of the caret at the end of the selection, in local coordinates.
+///
+/// At a boundary between runs of different directions, the caret offset has two positions on screen: after the
+/// cluster that precedes it in logical order, and before the cluster that follows it. In this case the caret is
+/// split: the primary caret is where text in the base direction of the paragraph goes, and the secondary caret
+/// is where text in the opposite direction goes.
+fn caret_rects(state: &mut TextEditState) -> Option<(Rect, Option<Rect>)> {
+    let offset = state.caret_offset();
+    let text = &state.display_text;
+    let caret = |info: &GlyphClusterInfo, leading: bool| {
+        let x = cluster_edge(info, leading);
+        Rect::new(x, info.bounds.top as f64, x + 1.0, info.bounds.bottom as f64)
+    };
+
+    let prev_offset = prev_grapheme_cluster(text, offset);
+    let after_line_break = text[..offset].ends_with('\n');
+    let next = state.paragraph.inner.get_glyph_cluster_at(offset);
+    let prev = prev_offset
+        .filter(|_| !after_line_break)
+        .and_then(|prev| state.paragraph.inner.get_glyph_cluster_at(prev));
+    let same_line = match (&prev, &next) {
+        (Some(prev), Some(_)) => {
+            let lines = state.paragraph.inner.get_line_metrics();
+            line_index_at(&lines, prev.text_range.start) == line_index_at(&lines, offset)
+        }
+        _ => false,
+    };
+
+    match (prev, next) {
+        (Some(prev), Some(next)) if same_line => {
+            let trailing = caret(&prev, false);
+            let leading = caret(&next, true);
+            if (trailing.x0 - leading.x0).abs() < 1.0 {
+                Some((leading, None))
+            } else if (prev.position == SkTextDirection::RTL) == state.base_direction.is_rtl() {
+                Some((trailing, Some(leading)))
+            } else {
+                Some((leading, Some(trailing)))
             }
-            State::NotAlnum => {
-                return pos;
+        }
+        (_, Some(next)) => Some((caret(&next, true), None)),
+        (Some(prev), None) => Some((caret(&prev, false), None)),
+        (None, None) => {
+            let x = empty_line_x(state);
+            if state.display_text.is_empty() {
+                return Some((Rect::new(x, 0.0, x + 1.0, state.paragraph.inner.height() as f64), None));
             }
+            // after a line break at the end of the text: at the start of the next line
+            let info = state.paragraph.inner.get_glyph_cluster_at(prev_offset?)?;
+            let top = info.bounds.bottom as f64;
+            Some((Rect::new(x, top, x + 1.0, top + info.bounds.height() as f64), None))
         }
-        pos -= ch.len_utf8();
     }
-    pos
 }
 
+/// Returns the rectangle of the (primary) caret at the end of the selection, in local coordinates.
+fn caret_rect(state: &mut TextEditState) -> Option<Rect> {
+    caret_rects(state).map(|(primary, _)| primary)
+}
+
+/// Returns the index of the line that contains the specified text offset.
+///
+/// An offset at a soft line break belongs to the line after the break.
+fn line_index_at(lines: &[LineMetrics], offset: usize) -> usize {
+    lines
+        .iter()
+        .position(|line| offset < line.end_including_newline)
+        .unwrap_or(lines.len().saturating_sub(1))
+}
+
+const CARET_BLINK_INITIAL_DELAY: Duration = Duration::from_secs(1);
+const CARET_BLINK_INTERVAL: Duration = Duration::from_millis(500);
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 enum Gesture {
     CharacterSelection,
     WordSelection { anchor: Selection },
+    LineSelection { anchor: Selection },
+    ParagraphSelection { anchor: Selection },
 }
 
 /// Single- or multiline text editor.
 pub struct TextEdit {
     element: Element,
+    weak_this: RefCell<Weak<TextEdit>>,
     selection_changed: Handler<Selection>,
+    text_changed: Handler<TextChange>,
     state: RefCell<TextEditState>,
     gesture: Cell<Option<Gesture>>,
     blink_phase: Cell<bool>,
     blink_reset: Cell<bool>,
+    /// Whether a task will report the pending changes with `text_changed`.
+    text_changed_scheduled: Cell<bool>,
 }
 
 impl TextEdit {
     pub fn new() -> Rc<TextEdit> {
         let text_edit = Element::new_derived(|element| TextEdit {
             element,
+            weak_this: RefCell::new(Weak::new()),
             selection_changed: Handler::new(),
+            text_changed: Handler::new(),
             state: RefCell::new(TextEditState {
-                text: String::new(),
+                buffer: TextBuffer::new(),
+                display_text: String::new(),
                 selection: Selection::empty(0),
+                preedit: None,
+                undo_stack: UndoStack::default(),
+                pending_changes: Vec::new(),
+                input_filter: None,
+                validator: None,
+                max_length: None,
+                read_only: false,
+                single_line: false,
+                preferred_x: None,
+                reveal_caret: false,
+                base_direction: TextDirection::LeftToRight,
+                caret_x: None,
                 text_style: TextStyle::default(),
                 last_available_width: 0.0,
-                paragraph: FormattedText::default().inner,
+                paragraph: FormattedText::default(),
                 selection_color: Color::from_rgba_u8(0, 0, 255, 80),
                 caret_color: Color::from_rgba_u8(255, 255, 0, 255),
                 relayout: true,
             }),
             blink_phase: Cell::new(true),
             blink_reset: Cell::new(false),
+            text_changed_scheduled: Cell::new(false),
             gesture: Cell::new(None),
         });
+        text_edit.weak_this.replace(Rc::downgrade(&text_edit));
 
         text_edit.set_tab_focusable(true);
+        text_edit.set_accepts_text_input(true);
+        for (name, shortcut) in [
+            (UNDO, "Ctrl+Z"),
+            (REDO, "Ctrl+Y"),
+            (CUT, "Ctrl+X"),
+            (COPY, "Ctrl+C"),
+            (PASTE, "Ctrl+V"),
+            (SELECT_ALL, "Ctrl+A"),
+        ] {
+            text_edit.add_command(Command::new(name).with_shortcut(shortcut.parse().unwrap()));
+        }
 
         // spawn the caret blinker task
         let this_weak = Rc::downgrade(&text_edit);
@@ -243,6 +523,8 @@ impl TextEdit {
     pub fn set_selection(&self, selection: Selection) -> bool {
         // TODO clamp selection to text length
         let this = &mut *self.state.borrow_mut();
+        this.preferred_x = None;
+        this.caret_x = None;
         if this.selection != selection {
             this.selection = selection;
             self.mark_needs_repaint();
@@ -254,23 +536,129 @@ impl TextEdit {
 
     /// Returns the current text.
     pub fn text(&self) -> String {
-        self.state.borrow().text.clone()
+        self.state.borrow().buffer.to_string()
     }
 
     /// Sets the current text.
+    ///
+    /// This clears the undo history. In single-line mode, line breaks are replaced by spaces.
+    /// The change is not reported by `text_changed`.
     pub fn set_text(&self, text: impl Into<String>) {
         // TODO we could compare the previous and new text
         // to relayout only affected lines.
         let this = &mut *self.state.borrow_mut();
-        this.text = text.into();
+        let mut text = text.into();
+        if this.single_line {
+            text = text.replace("\r\n", " ").replace(['\n', '\r'], " ");
+        }
+        this.buffer = TextBuffer::from(text.as_str());
+        this.selection = Selection::empty(this.selection.end.min(text.len()));
+        if !text.is_char_boundary(this.selection.end) {
+            this.selection = Selection::empty(0);
+        }
+        this.preedit = None;
+        this.preferred_x = None;
+        this.caret_x = None;
+        this.undo_stack = UndoStack::default();
+        this.pending_changes.clear();
+        this.rebuild_paragraph();
+        this.relayout = true;
+        self.mark_needs_relayout();
+    }
+
+    /// Sets a function that transforms the text entered by the user before it is inserted
+    /// (e.g. `digits_only`).
+    pub fn set_input_filter(&self, filter: Option<InputFilter>) {
+        self.state.borrow_mut().input_filter = filter;
+    }
+
+    /// Sets a function that decides whether an edit made by the user is accepted, given the resulting text.
+    pub fn set_validator(&self, validator: Option<TextValidator>) {
+        self.state.borrow_mut().validator = validator;
+    }
+
+    /// Sets the maximum number of chars that the user can enter.
+    ///
+    /// This doesn't truncate the current text.
+    pub fn set_max_length(&self, max_length: Option<usize>) {
+        self.state.borrow_mut().max_length = max_length;
+    }
+
+    /// Prevents the user from modifying the text. The text can still be selected and copied.
+    pub fn set_read_only(&self, read_only: bool) {
+        self.state.borrow_mut().read_only = read_only;
+        self.set_accepts_text_input(!read_only);
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.state.borrow().read_only
+    }
+
+    /// Restricts the text to a single line.
+    ///
+    /// In single-line mode, `Enter` isn't handled by the text edit, and line breaks in inserted text are replaced
+    /// by spaces.
+    pub fn set_single_line(&self, single_line: bool) {
+        let multiline = {
+            let this = &mut *self.state.borrow_mut();
+            this.single_line = single_line;
+            this.buffer.is_multiline()
+        };
+        if single_line && multiline {
+            self.set_text(self.text());
+        }
+    }
+
+    pub fn is_single_line(&self) -> bool {
+        self.state.borrow().single_line
+    }
+
+    /// Sets the base direction of the text, which determines its alignment, the order of runs of
+    /// mixed-direction text, and the direction of word navigation with the arrow keys.
+    pub fn set_base_direction(&self, direction: TextDirection) {
+        let this = &mut *self.state.borrow_mut();
+        if this.base_direction != direction {
+            this.base_direction = direction;
+            this.caret_x = None;
+            this.rebuild_paragraph();
+            this.relayout = true;
+            self.mark_needs_relayout();
+        }
+    }
+
+    pub fn base_direction(&self) -> TextDirection {
+        self.state.borrow().base_direction
+    }
+
+    /// Sets the text being composed with the input method, displayed in place of the selection.
+    ///
+    /// An empty text ends the composition.
+    fn set_preedit(&self, text: &str, cursor: Option<(usize, usize)>) {
+        let this = &mut *self.state.borrow_mut();
+        if this.preedit.is_none() && text.is_empty() {
+            return;
+        }
+        this.preedit = (!text.is_empty()).then(|| Preedit {
+            text: text.to_string(),
+            cursor,
+        });
         this.rebuild_paragraph();
         this.relayout = true;
         self.mark_needs_relayout();
     }
 
+    /// Reports the position of the caret to the input method.
+    fn update_ime_cursor_area(&self) {
+        let caret = caret_rect(&mut self.state.borrow_mut());
+        if let Some(caret) = caret {
+            self.set_ime_cursor_area(caret);
+        }
+    }
+
     pub fn get_text_offset_at_point(&self, point: Point) -> usize {
         let this = &mut *self.state.borrow_mut();
         this.paragraph
+            .inner
             .get_glyph_position_at_coordinate(point.to_skia())
             .position as usize
     }
@@ -280,7 +668,7 @@ impl TextEdit {
         // TODO set cursor position based on point
         let this = &mut *self.state.borrow_mut();
         let prev_selection = this.selection;
-        let pos = this.paragraph.get_glyph_position_at_coordinate(point.to_skia());
+        let pos = this.paragraph.inner.get_glyph_position_at_coordinate(point.to_skia());
         if keep_anchor {
             this.selection.end = pos.position as usize;
         } else {
@@ -295,9 +683,8 @@ impl TextEdit {
 
     pub fn select_word_under_cursor(&self) {
         let this = &mut *self.state.borrow_mut();
-        let text = &this.text;
         let selection = this.selection;
-        let range = this.paragraph.get_word_boundary(selection.end as u32);
+        let range = this.paragraph.inner.get_word_boundary(selection.end as u32);
         this.selection = Selection {
             start: range.start,
             end: range.end,
@@ -306,13 +693,18 @@ impl TextEdit {
     }
 
     pub fn select_word_at_offset_with_anchor(&self, offset: usize, anchor_selection: Selection) -> bool {
+        let range = self.state.borrow().paragraph.inner.get_word_boundary(offset as u32);
+        self.select_range_with_anchor(range, anchor_selection)
+    }
+
+    /// Selects the union of a range and the anchor selection of a selection gesture.
+    fn select_range_with_anchor(&self, range: Range<usize>, anchor_selection: Selection) -> bool {
         let this = &mut *self.state.borrow_mut();
-        let range = this.paragraph.get_word_boundary(offset as u32);
-        let word = Selection {
+        let range = Selection {
             start: range.start,
             end: range.end,
         };
-        let new_selection = add_selections(anchor_selection, word);
+        let new_selection = add_selections(anchor_selection, range);
         if new_selection != this.selection {
             this.selection = new_selection;
             self.mark_needs_repaint();
@@ -320,13 +712,30 @@ impl TextEdit {
         } else {
             false
         }
+    }
+
+    /// Returns the range of the visual line containing `offset`, excluding the line break.
+    ///
+    /// NOTE: valid only after first layout.
+    fn line_range_at(&self, offset: usize) -> Range<usize> {
+        let this = self.state.borrow();
+        let lines = this.paragraph.inner.get_line_metrics();
+        match lines.get(line_index_at(&lines, offset)) {
+            Some(line) => line.start_index..line.end_index.min(this.buffer.len()),
+            None => 0..0,
+        }
+    }
 
+    /// Returns the range of the paragraph containing `offset`, i.e. the text between two line breaks.
+    fn paragraph_range_at(&self, offset: usize) -> Range<usize> {
+        let this = self.state.borrow();
+        this.buffer.line_start(offset)..this.buffer.line_end(offset)
     }
 
     /// Moves the cursor to the next or previous word boundary.
     pub fn move_cursor_to_next_word(&self, keep_anchor: bool) {
         let this = &mut *self.state.borrow_mut();
-        this.selection.end = next_word_boundary(&this.text, this.selection.end);
+        this.selection.end = this.buffer.next_word_boundary(this.selection.end);
         if !keep_anchor {
             this.selection.start = this.selection.end;
         }
@@ -334,7 +743,7 @@ impl TextEdit {
 
     pub fn move_cursor_to_prev_word(&self, keep_anchor: bool) {
         let this = &mut *self.state.borrow_mut();
-        this.selection.end = prev_word_boundary(&this.text, this.selection.end);
+        this.selection.end = this.buffer.prev_word_boundary(this.selection.end);
         if !keep_anchor {
             this.selection.start = this.selection.end;
         }
@@ -342,7 +751,7 @@ impl TextEdit {
 
     pub fn move_cursor_to_next_grapheme(&self, keep_anchor: bool) {
         let this = &mut *self.state.borrow_mut();
-        this.selection.end = next_grapheme_cluster(&this.text, this.selection.end).unwrap_or(this.selection.end);
+        this.selection.end = this.buffer.next_grapheme_boundary(this.selection.end).unwrap_or(this.selection.end);
         if !keep_anchor {
             this.selection.start = this.selection.end;
         }
@@ -350,29 +759,402 @@ impl TextEdit {
 
     pub fn move_cursor_to_prev_grapheme(&self, keep_anchor: bool) {
         let this = &mut *self.state.borrow_mut();
-        this.selection.end = prev_grapheme_cluster(&this.text, this.selection.end).unwrap_or(this.selection.end);
+        this.selection.end = this.buffer.prev_grapheme_boundary(this.selection.end).unwrap_or(this.selection.end);
         if !keep_anchor {
             this.selection.start = this.selection.end;
         }
     }
 
-    /// Selects the line under the cursor.
-    pub fn select_line_under_cursor(&self) {
+    /// Moves the cursor to the grapheme cluster on the left or on the right of the caret, in visual order.
+    ///
+    /// In mixed-direction text, this moves the cursor backwards in the text when crossing runs of the opposite
+    /// direction. At the visual start or end of a line, the cursor continues on the previous or next line,
+    /// depending on the base direction.
+    ///
+    /// NOTE: valid only after first layout.
+    pub fn move_cursor_visually(&self, right: bool, keep_anchor: bool) {
         let this = &mut *self.state.borrow_mut();
-        let text = &this.text;
-        let selection = this.selection;
-        let start = text[..selection.end].rfind('\n').map_or(0, |i| i + 1);
-        let end = text[selection.end..]
-            .find('\n')
-            .map_or(text.len(), |i| selection.end + i);
-        this.selection = Selection { start, end };
-        self.mark_needs_repaint();
+        let Some((primary, secondary)) = caret_rects(this) else { return };
+        // at direction boundaries, start from the caret closest to where the last horizontal movement left it
+        // (not an exact comparison: `caret_x` comes from glyph bounds, which aren't computed like caret rects)
+        let from = match (this.caret_x, secondary) {
+            (Some(x), Some(secondary)) if (x - secondary.x0).abs() < (x - primary.x0).abs() => secondary,
+            _ => primary,
+        };
+        let y = from.center().y;
+        let probe = if right { from.x0 + 0.5 } else { from.x0 - 0.5 };
+        let cluster = this
+            .paragraph
+            .inner
+            .get_closest_glyph_cluster_at(Point::new(probe, y).to_skia())
+            .filter(|info| {
+                let (top, bottom) = (info.bounds.top as f64, info.bounds.bottom as f64);
+                let on_side = if right {
+                    info.bounds.right as f64 > probe
+                } else {
+                    (info.bounds.left as f64) < probe
+                };
+                top <= y && y <= bottom && on_side
+            });
+
+        let end = this.selection.end;
+        match cluster {
+            Some(info) => {
+                // moving over a cluster in its own direction moves forward in the text
+                let forward = (info.position == SkTextDirection::LTR) == right;
+                this.selection.end = if forward { info.text_range.end } else { info.text_range.start };
+                this.caret_x = Some(if right { info.bounds.right as f64 } else { info.bounds.left as f64 });
+            }
+            None => {
+                // visual end of the line: continue on the next or previous line
+                let forward = right != this.base_direction.is_rtl();
+                let next = if forward {
+                    this.buffer.next_grapheme_boundary(end)
+                } else {
+                    this.buffer.prev_grapheme_boundary(end)
+                };
+                this.selection.end = next.unwrap_or(end);
+                this.caret_x = None;
+            }
+        }
+        if !keep_anchor {
+            this.selection.start = this.selection.end;
+        }
+    }
+
+    /// Moves the cursor to the start of the line, or to the start of the text.
+    pub fn move_cursor_to_line_start(&self, whole_text: bool, keep_anchor: bool) {
+        let this = &mut *self.state.borrow_mut();
+        this.selection.end = if whole_text {
+            0
+        } else {
+            this.buffer.line_start(this.selection.end)
+        };
+        if !keep_anchor {
+            this.selection.start = this.selection.end;
+        }
+    }
+
+    /// Moves the cursor to the end of the line, or to the end of the text.
+    pub fn move_cursor_to_line_end(&self, whole_text: bool, keep_anchor: bool) {
+        let this = &mut *self.state.borrow_mut();
+        this.selection.end = if whole_text {
+            this.buffer.len()
+        } else {
+            this.buffer.line_end(this.selection.end)
+        };
+        if !keep_anchor {
+            this.selection.start = this.selection.end;
+        }
+    }
+
+    /// Moves the cursor to the line above or below.
+    pub fn move_cursor_vertically(&self, up: bool, keep_anchor: bool) {
+        self.move_cursor_by_lines(if up { -1 } else { 1 }, keep_anchor);
+    }
+
+    /// Moves the cursor by a number of visual lines (negative to go up).
+    ///
+    /// Consecutive vertical movements keep the horizontal position that the caret had before the first one, even
+    /// when going through shorter lines. Moving past the first or last line moves the cursor to the start or end
+    /// of the text.
+    ///
+    /// NOTE: valid only after first layout.
+    pub fn move_cursor_by_lines(&self, lines: isize, keep_anchor: bool) {
+        let this = &mut *self.state.borrow_mut();
+        let Some(caret) = caret_rect(this) else { return };
+        let x = *this.preferred_x.get_or_insert(caret.x0);
+        let line_metrics = this.paragraph.inner.get_line_metrics();
+        let target = line_index_at(&line_metrics, this.selection.end) as isize + lines;
+        this.selection.end = if target < 0 {
+            0
+        } else if let Some(line) = line_metrics.get(target as usize) {
+            // aim for the middle of the target line
+            let y = line.baseline + (line.descent - line.ascent) / 2.0;
+            let pos = this.paragraph.inner.get_glyph_position_at_coordinate(Point::new(x, y).to_skia());
+            (pos.position as usize)
+                .clamp(line.start_index, line.end_index)
+                .min(this.buffer.len())
+        } else {
+            this.buffer.len()
+        };
+        if !keep_anchor {
+            this.selection.start = this.selection.end;
+        }
+    }
+
+    /// Moves the cursor up or down by the height of the visible area of the text edit.
+    pub fn move_cursor_by_page(&self, up: bool, keep_anchor: bool) {
+        let line_height = {
+            let this = &mut *self.state.borrow_mut();
+            let Some(caret) = caret_rect(this) else { return };
+            caret.height().max(1.0)
+        };
+        let lines = (self.page_height() / line_height).floor().max(1.0) as isize;
+        self.move_cursor_by_lines(if up { -lines } else { lines }, keep_anchor);
+    }
+
+    /// Returns the height of the visible area of the text edit: the height of the clip rectangle of the nearest
+    /// clipping ancestor (e.g. an enclosing scroll view), or the height of the text edit if there's none.
+    fn page_height(&self) -> f64 {
+        let mut parent = self.parent();
+        while let Some(p) = parent {
+            if let Some(clip_rect) = p.clip_rect() {
+                return clip_rect.height();
+            }
+            parent = p.parent();
+        }
+        self.geometry().size.height
+    }
+
+    /// Scrolls the enclosing scroll views so that the caret is visible.
+    fn reveal_caret(&self) {
+        let this = &mut *self.state.borrow_mut();
+        if this.relayout {
+            // the new text isn't laid out yet
+            this.reveal_caret = true;
+        } else if let Some(caret) = caret_rect(this) {
+            self.scroll_into_view(caret);
+        }
+    }
+
+    pub fn select_all(&self) -> bool {
+        let len = self.state.borrow().buffer.len();
+        self.set_selection(Selection { start: 0, end: len })
+    }
+
+    /// Replaces a range of the text on behalf of the user, recording the change in the undo history.
+    ///
+    /// The inserted text is subject to the input filter, the maximum length and the validator.
+    /// The caret is placed after the inserted text. Returns `false` if the edit was rejected.
+    fn edit(&self, range: Range<usize>, text: &str, kind: EditKind) -> bool {
+        let this = &mut *self.state.borrow_mut();
+        let Some(text) = this.sanitize_input(range.clone(), text) else { return false };
+        if range.is_empty() && text.is_empty() {
+            return false;
+        }
+        let change = TextChange {
+            offset: range.start,
+            removed: this.buffer.slice(range.clone()),
+            inserted: text,
+        };
+        if !this.validate(&change) {
+            return false;
+        }
+        this.buffer.apply(&change);
+        let edit = Edit {
+            selection_before: this.selection,
+            selection_after: Selection::empty(range.start + change.inserted.len()),
+            change: change.clone(),
+        };
+        this.selection = edit.selection_after;
+        this.undo_stack.push(edit, kind, application::now());
+        this.pending_changes.push(change);
+        this.rebuild_paragraph();
+        this.relayout = true;
+        self.mark_needs_relayout();
+        self.schedule_text_changed();
+        true
+    }
+
+    /// Replaces the selected text.
+    pub fn insert(&self, text: &str) {
+        let range = self.selection().byte_range();
+        self.edit(range, text, EditKind::Other);
+    }
+
+    /// Deletes the selected text, or the grapheme cluster (or word) before the caret if the selection is empty.
+    pub fn delete_backward(&self, word: bool) {
+        let selection = self.selection();
+        let range = if selection.is_empty() {
+            let this = self.state.borrow();
+            let buffer = &this.buffer;
+            let start = if word {
+                buffer.prev_word_boundary(selection.end)
+            } else {
+                buffer.prev_grapheme_boundary(selection.end).unwrap_or(selection.end)
+            };
+            start..selection.end
+        } else {
+            selection.byte_range()
+        };
+        if !range.is_empty() {
+            self.edit(range, "", EditKind::DeleteBackward);
+        }
+    }
+
+    /// Deletes the selected text, or the grapheme cluster (or word) after the caret if the selection is empty.
+    pub fn delete_forward(&self, word: bool) {
+        let selection = self.selection();
+        let range = if selection.is_empty() {
+            let this = self.state.borrow();
+            let buffer = &this.buffer;
+            let end = if word {
+                buffer.next_word_boundary(selection.end)
+            } else {
+                buffer.next_grapheme_boundary(selection.end).unwrap_or(selection.end)
+            };
+            selection.end..end
+        } else {
+            selection.byte_range()
+        };
+        if !range.is_empty() {
+            self.edit(range, "", EditKind::DeleteForward);
+        }
+    }
+
+    fn apply_history_edit(&self, edit: Edit, undo: bool) {
+        let this = &mut *self.state.borrow_mut();
+        let change = if undo {
+            this.selection = edit.selection_before;
+            edit.change.inverse()
+        } else {
+            this.selection = edit.selection_after;
+            edit.change
+        };
+        this.buffer.apply(&change);
+        this.pending_changes.push(change);
+        this.rebuild_paragraph();
+        this.relayout = true;
+        self.mark_needs_relayout();
+        self.schedule_text_changed();
+    }
+
+    /// Reports the pending changes with `text_changed` once the current event handler, if any, is done.
+    ///
+    /// Changes made while handling an event are reported at the end of `event`, before `selection_changed`.
+    /// This reports the changes made by calling methods like `insert` or `undo` outside of event handlers.
+    fn schedule_text_changed(&self) {
+        if self.text_changed_scheduled.replace(true) {
+            return;
+        }
+        let this_weak = self.weak_this.borrow().clone();
+        spawn(async move {
+            if let Some(this) = this_weak.upgrade() {
+                this.text_changed_scheduled.set(false);
+                this.emit_text_changed().await;
+            }
+        });
+    }
+
+    async fn emit_text_changed(&self) {
+        let changes = mem::take(&mut self.state.borrow_mut().pending_changes);
+        for change in changes {
+            self.text_changed.emit(change).await;
+        }
+    }
+
+    /// Reverts the last edit. Returns `false` if there's nothing to undo.
+    pub fn undo(&self) -> bool {
+        if self.is_read_only() {
+            return false;
+        }
+        let edit = self.state.borrow_mut().undo_stack.undo();
+        let Some(edit) = edit else { return false };
+        self.apply_history_edit(edit, true);
+        true
+    }
+
+    /// Reapplies the last undone edit. Returns `false` if there's nothing to redo.
+    pub fn redo(&self) -> bool {
+        if self.is_read_only() {
+            return false;
+        }
+        let edit = self.state.borrow_mut().undo_stack.redo();
+        let Some(edit) = edit else { return false };
+        self.apply_history_edit(edit, false);
+        true
+    }
+
+    pub fn can_undo(&self) -> bool {
+        let this = self.state.borrow();
+        !this.read_only && !this.undo_stack.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        let this = self.state.borrow();
+        !this.read_only && !this.undo_stack.redo.is_empty()
+    }
+
+    /// Copies the selected text to the clipboard.
+    pub fn copy(&self) {
+        let this = self.state.borrow();
+        if !this.selection.is_empty() {
+            clipboard::set_text(this.buffer.slice(this.selection.byte_range()));
+        }
+    }
+
+    /// Moves the selected text to the clipboard.
+    pub fn cut(&self) {
+        let selection = self.selection();
+        if self.is_read_only() || selection.is_empty() {
+            return;
+        }
+        self.copy();
+        self.edit(selection.byte_range(), "", EditKind::Other);
+    }
+
+    /// Replaces the selection with the text in the clipboard.
+    pub fn paste(&self) {
+        if let Some(text) = clipboard::get_text() {
+            self.insert(&text);
+        }
+    }
+
+    /// Runs one of the editing commands. Returns whether the selection changed.
+    fn run_command(&self, command: &str) -> bool {
+        self.state.borrow_mut().undo_stack.close_group();
+        match command {
+            UNDO => self.undo(),
+            REDO => self.redo(),
+            CUT => {
+                let has_selection = !self.selection().is_empty();
+                self.cut();
+                has_selection
+            }
+            COPY => {
+                self.copy();
+                false
+            }
+            PASTE => {
+                self.paste();
+                true
+            }
+            SELECT_ALL => self.select_all(),
+            _ => false,
+        }
+    }
+
+    /// Selects the visual line under the cursor.
+    ///
+    /// NOTE: valid only after first layout.
+    pub fn select_line_under_cursor(&self) {
+        let range = self.line_range_at(self.selection().end);
+        self.set_selection(Selection {
+            start: range.start,
+            end: range.end,
+        });
+    }
+
+    /// Selects the paragraph under the cursor, i.e. the text between two line breaks.
+    pub fn select_paragraph_under_cursor(&self) {
+        let range = self.paragraph_range_at(self.selection().end);
+        self.set_selection(Selection {
+            start: range.start,
+            end: range.end,
+        });
     }
 
     /// Emitted when the selection changes as a result of user interaction.
     pub async fn selection_changed(&self) -> Selection {
         self.selection_changed.wait().await
     }
+
+    /// Emitted for each change of the text made by the user (typing, deletion, paste, undo...), or by the editing
+    /// methods (`insert`, `paste`, `undo`...) when called directly. Changes made with `set_text` are not reported.
+    pub async fn text_changed(&self) -> TextChange {
+        self.text_changed.wait().await
+    }
 }
 
 impl Deref for TextEdit {
@@ -424,6 +1206,21 @@ impl Visual for TextEdit {
         &self.element
     }
 
+    fn intrinsic_sizes(&self) -> IntrinsicSizes {
+        let this = &mut *self.state.borrow_mut();
+        let sizes = paragraph_intrinsic_sizes(&mut this.paragraph.inner);
+        // restore the paragraph layout, since `layout` may be skipped if the constraints didn't change
+        this.paragraph.layout(this.last_available_width);
+        sizes
+    }
+
+    fn intrinsic_height(&self, width: f64) -> f64 {
+        let this = &mut *self.state.borrow_mut();
+        let height = paragraph_height_for_width(&mut this.paragraph.inner, width);
+        this.paragraph.layout(this.last_available_width);
+        height
+    }
+
     fn layout(&self, _children: &[Rc<dyn Visual>], constraints: &BoxConstraints) -> Geometry {
         let this = &mut *self.state.borrow_mut();
 
@@ -432,14 +1229,20 @@ impl Visual for TextEdit {
 
         let invalidate_layout = this.relayout || this.last_available_width != available_width;
         if invalidate_layout {
-            this.paragraph.layout(available_width as f32);
+            this.paragraph.layout(available_width);
         }
         this.relayout = false;
         this.last_available_width = available_width;
+        if mem::take(&mut this.reveal_caret) {
+            // this is applied after the layout of the window, once the position of the text edit is known
+            if let Some(caret) = caret_rect(this) {
+                self.scroll_into_view(caret);
+            }
+        }
 
-        let w = this.paragraph.longest_line() as f64;
-        let h = this.paragraph.height() as f64;
-        let alphabetic_baseline = this.paragraph.alphabetic_baseline();
+        let w = this.paragraph.inner.longest_line() as f64;
+        let h = this.paragraph.inner.height() as f64;
+        let alphabetic_baseline = this.paragraph.inner.alphabetic_baseline();
         let unconstrained_size = Size::new(w, h);
         let size = constraints.constrain(unconstrained_size);
 
@@ -461,37 +1264,48 @@ impl Visual for TextEdit {
             canvas.draw_rect(bounds.to_rect().to_skia(), &paint);
 
             // paint the paragraph
-            this.paragraph.paint(canvas, Point::ZERO.to_skia());
-            // paint the selection rectangles
-            let selection_rects = this.paragraph.get_rects_for_range(
-                this.selection.min()..this.selection.max(),
-                RectHeightStyle::Tight,
-                RectWidthStyle::Tight,
-            );
-            let selection_paint = Paint::from(this.selection_color).to_sk_paint(bounds.to_rect());
-            for text_box in selection_rects {
-                canvas.draw_rect(text_box.rect, &selection_paint);
+            this.paragraph.inner.paint(canvas, Point::ZERO.to_skia());
+            // underline the IME composition
+            if let Some(preedit_len) = this.preedit.as_ref().map(|preedit| preedit.text.len()) {
+                let start = this.selection.min();
+                let underline_paint = Paint::from(this.text_style.color).to_sk_paint(bounds.to_rect());
+                for r in this.paragraph.get_rects_for_range(start..start + preedit_len) {
+                    let underline = Rect::new(r.x0, r.y1 - 1.0, r.x1, r.y1);
+                    canvas.draw_rect(underline.to_skia(), &underline_paint);
+                }
+            }
+            // paint the selection rectangles (one per run in mixed-direction text), unless the selection is
+            // hidden by the IME composition
+            if this.preedit.is_none() {
+                let selection_paint = Paint::from(this.selection_color).to_sk_paint(bounds.to_rect());
+                for r in this.paragraph.get_selection_rects(this.selection.byte_range()) {
+                    canvas.draw_rect(r.to_skia(), &selection_paint);
+                }
             }
 
             if self.has_focus() && self.blink_phase.get() {
-                if let Some(info) = this.paragraph.get_glyph_cluster_at(this.selection.end) {
-                    let caret_rect = Rect::from_origin_size(
-                        Point::new((info.bounds.left as f64).round(), (info.bounds.top as f64).round()),
-                        Size::new(1.0, info.bounds.height() as f64),
-                    );
-                    //eprintln!("caret_rect: {:?}", caret_rect);
+                if let Some((primary, secondary)) = caret_rects(this) {
+                    let snap = |r: Rect| Rect::from_origin_size(Point::new(r.x0.round(), r.y0.round()), r.size());
                     let caret_paint = Paint::from(this.caret_color).to_sk_paint(bounds.to_rect());
-                    canvas.draw_rect(caret_rect.to_skia(), &caret_paint);
+                    canvas.draw_rect(snap(primary).to_skia(), &caret_paint);
+                    // the secondary caret of a split caret is dimmed
+                    if let Some(secondary) = secondary {
+                        let secondary_paint =
+                            Paint::from(this.caret_color.with_alpha(0.5)).to_sk_paint(bounds.to_rect());
+                        canvas.draw_rect(snap(secondary).to_skia(), &secondary_paint);
+                    }
                 }
             }
         });
     }
 
-    async fn event(&self, event: &mut Event)
+    async fn event(&self, ctx: &mut EventCtx, event: &mut Event)
     where
         Self: Sized,
     {
         let mut selection_changed = false;
+        let mut vertical_move = false;
+        let mut visual_move = false;
         match event {
             Event::PointerDown(event) => {
                 let pos = event.local_position();
@@ -504,11 +1318,22 @@ impl Visual for TextEdit {
                         anchor: self.selection(),
                     }));
                 } else if event.repeat_count == 3 {
-                    // TODO select line under cursor
+                    self.select_line_under_cursor();
+                    selection_changed = true;
+                    self.gesture.set(Some(Gesture::LineSelection {
+                        anchor: self.selection(),
+                    }));
+                } else if event.repeat_count >= 4 {
+                    self.select_paragraph_under_cursor();
+                    selection_changed = true;
+                    self.gesture.set(Some(Gesture::ParagraphSelection {
+                        anchor: self.selection(),
+                    }));
                 } else {
                     selection_changed |= self.set_cursor_at_point(pos, false);
                     self.gesture.set(Some(Gesture::CharacterSelection));
                 }
+                self.state.borrow_mut().undo_stack.close_group();
                 self.reset_blink();
                 self.set_focus().await;
                 self.set_pointer_capture();
@@ -525,6 +1350,14 @@ impl Visual for TextEdit {
                         let text_offset = self.get_text_offset_at_point(pos);
                         selection_changed |=  self.select_word_at_offset_with_anchor(text_offset, anchor);
                     }
+                    Some(Gesture::LineSelection { anchor }) => {
+                        let range = self.line_range_at(self.get_text_offset_at_point(pos));
+                        selection_changed |= self.select_range_with_anchor(range, anchor);
+                    }
+                    Some(Gesture::ParagraphSelection { anchor }) => {
+                        let range = self.paragraph_range_at(self.get_text_offset_at_point(pos));
+                        selection_changed |= self.select_range_with_anchor(range, anchor);
+                    }
                     _ => {}
                 }
 
@@ -536,57 +1369,217 @@ impl Visual for TextEdit {
             Event::FocusGained => {
                 eprintln!("focus gained");
                 self.reset_blink();
+                self.update_ime_cursor_area();
             }
             Event::FocusLost => {
                 eprintln!("focus lost");
+                self.set_preedit("", None);
                 selection_changed |= self.set_selection(Selection::empty(0));
             }
+            Event::Ime(ImeEvent::Preedit(text, cursor)) => {
+                self.set_preedit(text, *cursor);
+                self.update_ime_cursor_area();
+                self.reset_blink();
+                ctx.set_handled();
+            }
+            Event::Ime(ImeEvent::Commit(text)) => {
+                // the committed text replaces the selection, in a single edit
+                self.set_preedit("", None);
+                self.edit(self.selection().byte_range(), text, EditKind::Typing);
+                selection_changed = true;
+                self.reset_blink();
+                ctx.set_handled();
+            }
+            Event::Ime(ImeEvent::Disabled) => {
+                self.set_preedit("", None);
+            }
+            Event::CommandQuery(query) => {
+                query.enabled = match query.command {
+                    UNDO => self.can_undo(),
+                    REDO => self.can_redo(),
+                    CUT => !self.is_read_only() && !self.selection().is_empty(),
+                    COPY => !self.selection().is_empty(),
+                    PASTE => !self.is_read_only(),
+                    _ => true,
+                };
+            }
+            Event::Command(command) => {
+                selection_changed |= self.run_command(*command);
+                self.reset_blink();
+                ctx.set_handled();
+            }
             Event::KeyDown(event) => {
                 let keep_anchor = event.modifiers.shift();
                 let word_nav = event.modifiers.ctrl();
+                let mut handled = true;
                 match event.key {
-                    Key::ArrowLeft => {
-                        // TODO bidi?
+                    Key::ArrowLeft | Key::ArrowRight => {
+                        let right = event.key == Key::ArrowRight;
                         if word_nav {
-                            self.move_cursor_to_prev_word(keep_anchor);
+                            // word navigation is in logical order, flipped in right-to-left paragraphs
+                            if right != self.base_direction().is_rtl() {
+                                self.move_cursor_to_next_word(keep_anchor);
+                            } else {
+                                self.move_cursor_to_prev_word(keep_anchor);
+                            }
                         } else {
-                            self.move_cursor_to_prev_grapheme(keep_anchor);
+                            self.move_cursor_visually(right, keep_anchor);
+                            visual_move = true;
                         }
-                        selection_changed = true;
-                        self.reset_blink();
                     }
-                    Key::ArrowRight => {
-                        if word_nav {
-                            self.move_cursor_to_next_word(keep_anchor);
+                    Key::ArrowUp | Key::ArrowDown | Key::PageUp | Key::PageDown => {
+                        let up = matches!(event.key, Key::ArrowUp | Key::PageUp);
+                        if matches!(event.key, Key::PageUp | Key::PageDown) {
+                            self.move_cursor_by_page(up, keep_anchor);
                         } else {
-                            self.move_cursor_to_next_grapheme(keep_anchor);
+                            self.move_cursor_vertically(up, keep_anchor);
                         }
-                        selection_changed = true;
-                        self.reset_blink();
+                        vertical_move = true;
+                    }
+                    Key::Home => self.move_cursor_to_line_start(word_nav, keep_anchor),
+                    Key::End => self.move_cursor_to_line_end(word_nav, keep_anchor),
+                    Key::Backspace => self.delete_backward(word_nav),
+                    Key::Delete => self.delete_forward(word_nav),
+                    // let single-line text edits' parents handle Enter (e.g. to validate a dialog)
+                    Key::Enter if self.is_single_line() => handled = false,
+                    Key::Enter => {
+                        self.edit(self.selection().byte_range(), "\n", EditKind::Typing);
                     }
+                    // alternative shortcut for redo
+                    Key::Character(ref s) if word_nav && keep_anchor && s.eq_ignore_ascii_case("z") => {
+                        self.run_command(REDO);
+                    }
+                    // don't insert characters of unhandled shortcuts
+                    Key::Character(_) if word_nav => handled = false,
                     Key::Character(ref s) => {
-                        // TODO don't do this, emit the changed text instead
-                        let this = &mut *self.state.borrow_mut();
-                        let mut text = this.text.clone();
-                        let selection = this.selection;
-                        text.replace_range(selection.byte_range(), &s);
-                        this.text = text;
-                        this.rebuild_paragraph();
-                        this.relayout = true;
-                        this.selection = Selection::empty(selection.min() + s.len());
-                        selection_changed = true;
-                        self.mark_needs_relayout();
-                        self.reset_blink();
+                        self.edit(self.selection().byte_range(), s, EditKind::Typing);
                     }
-                    _ => {}
+                    _ => handled = false,
+                }
+                if handled {
+                    if !matches!(event.key, Key::Character(_) | Key::Enter | Key::Backspace | Key::Delete) {
+                        // moving the caret ends the current group of undoable edits
+                        self.state.borrow_mut().undo_stack.close_group();
+                    }
+                    selection_changed = true;
+                    self.reset_blink();
+                    ctx.set_handled();
                 }
             }
             _ => {}
         }
 
+        self.emit_text_changed().await;
         if selection_changed {
+            {
+                let this = &mut *self.state.borrow_mut();
+                if !vertical_move {
+                    this.preferred_x = None;
+                }
+                if !visual_move {
+                    this.caret_x = None;
+                }
+            }
+            self.reveal_caret();
+            self.update_ime_cursor_area();
             self.mark_needs_repaint();
             self.selection_changed.emit(self.selection()).await;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::application::TestRuntime;
+
+    fn typed(offset: usize, text: &str) -> Edit {
+        Edit {
+            change: TextChange {
+                offset,
+                removed: String::new(),
+                inserted: text.to_string(),
+            },
+            selection_before: Selection::empty(offset),
+            selection_after: Selection::empty(offset + text.len()),
+        }
+    }
+
+    #[test]
+    fn undo_groups_typed_words() {
+        let mut stack = UndoStack::default();
+        let t0 = Instant::now();
+        for (i, ch) in "ab cd".chars().enumerate() {
+            stack.push(typed(i, &ch.to_string()), EditKind::Typing, t0);
+        }
+        // "ab", then " cd"
+        assert_eq!(stack.undo.len(), 2);
+        assert_eq!(stack.undo[1].change.inserted, " cd");
+
+        // a pause starts a new group
+        stack.push(typed(5, "e"), EditKind::Typing, t0 + UNDO_GROUP_TIMEOUT);
+        assert_eq!(stack.undo.len(), 3);
+
+        let edit = stack.undo().unwrap();
+        assert_eq!(edit.change.inserted, "e");
+        assert_eq!(stack.redo().unwrap(), edit);
+    }
+
+    fn type_text(rt: &TestRuntime, text_edit: &TextEdit, text: &str) {
+        rt.enter(|| {
+            for ch in text.chars() {
+                text_edit.edit(text_edit.selection().byte_range(), &ch.to_string(), EditKind::Typing);
+            }
+        });
+    }
+
+    #[test]
+    fn undo_groups_follow_application_clock() {
+        let mut rt = TestRuntime::new();
+        let text_edit = rt.enter(TextEdit::new);
+
+        type_text(&rt, &text_edit, "ab");
+        rt.advance(UNDO_GROUP_TIMEOUT / 2);
+        type_text(&rt, &text_edit, "c");
+        rt.advance(UNDO_GROUP_TIMEOUT);
+        type_text(&rt, &text_edit, "d");
+        assert_eq!(text_edit.text(), "abcd");
+
+        // "abc" was typed without pausing, "d" after a pause
+        rt.enter(|| {
+            assert!(text_edit.undo());
+            assert_eq!(text_edit.text(), "abc");
+            assert!(text_edit.undo());
+            assert_eq!(text_edit.text(), "");
+            assert!(!text_edit.undo());
+        });
+    }
+
+    #[test]
+    fn programmatic_edits_emit_text_changed() {
+        let mut rt = TestRuntime::new();
+        let text_edit = rt.enter(TextEdit::new);
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        rt.spawn({
+            let text_edit = text_edit.clone();
+            let changes = changes.clone();
+            async move {
+                loop {
+                    let change = text_edit.text_changed().await;
+                    changes.borrow_mut().push(change);
+                }
+            }
+        });
+        rt.run_until_stalled();
+
+        rt.enter(|| text_edit.insert("hello"));
+        rt.run_until_stalled();
+        assert_eq!(changes.borrow().len(), 1);
+        assert_eq!(changes.borrow()[0].inserted, "hello");
+
+        rt.enter(|| text_edit.undo());
+        rt.run_until_stalled();
+        assert_eq!(changes.borrow().len(), 2);
+        assert_eq!(changes.borrow()[1].removed, "hello");
+    }
+}
@@ -12,22 +12,29 @@ use std::time::Instant;
 
 use futures_util::StreamExt;
 use keyboard_types::{Key, KeyboardEvent};
-use kurbo::{Affine, Point, Rect, Size};
+use kurbo::{Affine, Point, Rect, Size, Vec2};
 use raw_window_handle::{HasWindowHandle, RawWindowHandle};
 use skia_safe::font::Edging;
 use skia_safe::{Font, FontMgr, FontStyle, Typeface};
-use tracing::info;
-use winit::dpi::{PhysicalPosition, PhysicalSize};
-use winit::event::{DeviceId, ElementState, KeyEvent, MouseButton, WindowEvent};
+use tracing::{info, warn};
+use winit::dpi::{LogicalPosition, LogicalSize, PhysicalPosition, PhysicalSize};
+use winit::event::{
+    DeviceId, ElementState, Ime, KeyEvent, MouseButton, MouseScrollDelta, Touch, TouchPhase, WindowEvent,
+};
 use winit::keyboard::KeyLocation;
+#[cfg(windows)]
 use winit::platform::windows::WindowBuilderExtWindows;
 
 use crate::app_globals::AppGlobals;
-use crate::application::{spawn, with_event_loop_window_target, WindowHandler};
-use crate::compositor::{ColorType, Layer};
+use crate::application::{spawn, wait_until, with_event_loop_window_target, WindowHandler};
+use crate::compositor::{ColorType, DrawableSurface, Layer};
 use crate::drawing::ToSkia;
-use crate::element::{AnyVisual, Element, Visual, WeakNullableElemPtr};
-use crate::event::{key_event_to_key_code, Event, PointerButton, PointerButtons, PointerEvent};
+use crate::element::{paint_subtree, AnyVisual, Element, Visual, WeakNullableElemPtr};
+use crate::event::{
+    key_event_to_key_code, CommandQuery, DragData, DragEvent, Event, EventCtx, EventPhase, EventRecorder,
+    EventRecording, FileDragEvent, GestureEvent, GesturePhase, ImeEvent, PointerButton, PointerButtons,
+    PointerEvent, PointerType, RecordedEventKind, WheelDelta, WheelEvent, MOUSE_POINTER_ID,
+};
 use crate::handler::Handler;
 use crate::layout::BoxConstraints;
 use crate::{application, Color, PaintCtx};
@@ -97,6 +104,45 @@ struct InputState {
     //prev_hit_test_result: Vec<HitTestEntry>,
 }
 
+/// State of the drag-and-drop operation in progress.
+struct DragSession {
+    /// The element that started the drag.
+    source: Weak<dyn Visual>,
+    /// The window of the source element.
+    source_window: Weak<WindowInner>,
+    data: DragData,
+    /// Visual painted under the pointer.
+    preview: Option<Rc<dyn Visual>>,
+    /// The window under the pointer, and the position of the pointer in this window.
+    current_window: Option<(Weak<WindowInner>, Point)>,
+    /// The innermost element under the pointer.
+    target: Option<Weak<dyn Visual>>,
+    /// Whether the target accepted the payload on the last `DragOver` event.
+    accepted: bool,
+}
+
+/// Maximum number of entries in the focus history of a window.
+const FOCUS_HISTORY_LEN: usize = 16;
+
+thread_local! {
+    /// The drag-and-drop operation in progress.
+    ///
+    /// There's at most one for the whole application, and it can move between windows.
+    static DRAG_SESSION: RefCell<Option<DragSession>> = const { RefCell::new(None) };
+    /// All the windows of the application, to look for drop targets outside the window where the drag started.
+    static WINDOWS: RefCell<Vec<Weak<WindowInner>>> = const { RefCell::new(Vec::new()) };
+}
+
+fn is_dragging() -> bool {
+    DRAG_SESSION.with_borrow(|session| session.is_some())
+}
+
+/// Returns whether a position in physical pixels relative to the client area is inside the window.
+fn contains_physical_point(window: &winit::window::Window, point: Point) -> bool {
+    let size = window.inner_size();
+    point.x >= 0.0 && point.y >= 0.0 && point.x < size.width as f64 && point.y < size.height as f64
+}
+
 pub(crate) struct WindowInner {
     weak_this: Weak<WindowInner>,
     close_requested: Handler<()>,
@@ -104,7 +150,14 @@ pub(crate) struct WindowInner {
     resized: Handler<PhysicalSize<u32>>,
     root: Rc<dyn Visual>,
     layer: Layer,
-    window: winit::window::Window,
+    /// The native window, or `None` for headless windows.
+    window: Option<winit::window::Window>,
+    /// Scale factor of headless windows (native windows get it from winit).
+    scale_factor: Cell<f64>,
+    /// Logical size of headless windows.
+    headless_size: Cell<Size>,
+    /// Set when a repaint has been requested on a headless window.
+    redraw_requested: Cell<bool>,
     hidden_before_first_draw: Cell<bool>,
     cursor_pos: Cell<Point>,
     last_physical_size: Cell<Size>,
@@ -113,17 +166,237 @@ pub(crate) struct WindowInner {
     pointer_capture: WeakNullableElemPtr,
     /// The widget that has the focus for keyboard events.
     focus: WeakNullableElemPtr,
+    /// Whether the focus was last moved with the keyboard (see `Element::has_focus_visible`).
+    focus_visible: Cell<bool>,
+    /// Previously focused widgets, most recent last. Used to restore the focus when the focused widget is removed.
+    focus_history: RefCell<Vec<Weak<dyn Visual>>>,
+    /// The widget under the pointer when files dragged from outside the application entered the window.
+    file_hover_target: WeakNullableElemPtr,
     background: Cell<Color>,
     active_popup: RefCell<Option<Weak<WindowInner>>>,
+    /// Records input events, if a recording is in progress.
+    recorder: RefCell<Option<EventRecorder>>,
+    /// Relayout boundaries whose subtree needs to be laid out again.
+    pending_relayouts: RefCell<Vec<Weak<dyn Visual>>>,
+    /// Elements and rectangles to scroll into view after the next layout.
+    pending_scroll_into_view: RefCell<Vec<(Weak<dyn Visual>, Rect)>>,
     // DEBUGGING
     last_kb_event: RefCell<Option<KeyboardEvent>>,
 }
 
 impl WindowInner {
+    fn new(
+        root: &Element,
+        layer: Layer,
+        window: Option<winit::window::Window>,
+        physical_size: Size,
+        scale_factor: f64,
+        background: Color,
+    ) -> Rc<WindowInner> {
+        let hidden_before_first_draw = window.is_some();
+        let shared = Rc::new_cyclic(|weak_this| WindowInner {
+            weak_this: weak_this.clone(),
+            close_requested: Handler::new(),
+            focus_changed: Handler::new(),
+            resized: Handler::new(),
+            root: root.rc(),
+            layer,
+            window,
+            scale_factor: Cell::new(scale_factor),
+            headless_size: Cell::new(Size::new(
+                physical_size.width / scale_factor,
+                physical_size.height / scale_factor,
+            )),
+            redraw_requested: Cell::new(true),
+            hidden_before_first_draw: Cell::new(hidden_before_first_draw),
+            cursor_pos: Cell::new(Default::default()),
+            last_physical_size: Cell::new(physical_size),
+            input_state: Default::default(),
+            pointer_capture: Default::default(),
+            focus: Default::default(),
+            focus_visible: Cell::new(false),
+            focus_history: RefCell::new(Vec::new()),
+            file_hover_target: Default::default(),
+            background: Cell::new(background),
+            active_popup: RefCell::new(None),
+            recorder: RefCell::new(None),
+            pending_relayouts: RefCell::new(Vec::new()),
+            pending_scroll_into_view: RefCell::new(Vec::new()),
+            last_kb_event: RefCell::new(None),
+        });
+
+        // Note: I don't really like the fact that elements themselves call back into the window
+        // to request a redraw. It would be better if the window could just listen for changes
+        // to the dirty flags. But since only one window is supposed to watch dirty flags,
+        // that would probably be an unnecessary complication.
+        let weak = Rc::downgrade(&shared);
+        WINDOWS.with_borrow_mut(|windows| {
+            windows.retain(|w| w.strong_count() > 0);
+            windows.push(weak.clone());
+        });
+        root.set_parent_window(WeakWindow { shared: weak });
+        shared
+    }
+
+    /// Returns the native window.
+    ///
+    /// # Panics
+    ///
+    /// If this is a headless window.
+    fn native_window(&self) -> &winit::window::Window {
+        self.window.as_ref().expect("not a native window")
+    }
+
+    fn request_redraw(&self) {
+        if let Some(ref window) = self.window {
+            window.request_redraw();
+        } else {
+            self.redraw_requested.set(true);
+        }
+    }
+
+    fn scale_factor(&self) -> f64 {
+        if let Some(ref window) = self.window {
+            window.scale_factor()
+        } else {
+            self.scale_factor.get()
+        }
+    }
+
+    /// Starts recording input events, discarding any recording in progress.
+    fn start_recording(&self) {
+        let recorder = EventRecorder::new(self.last_physical_size.get(), self.scale_factor());
+        self.recorder.replace(Some(recorder));
+    }
+
+    /// Stops recording input events and returns the recording.
+    fn stop_recording(&self) -> Option<EventRecording> {
+        self.recorder.take().map(EventRecorder::finish)
+    }
+
+    fn record(&self, kind: RecordedEventKind) {
+        if let Some(ref mut recorder) = *self.recorder.borrow_mut() {
+            recorder.record(kind);
+        }
+    }
+
+    fn record_event(&self, event: &Event) {
+        if self.recorder.borrow().is_some() {
+            if let Some(kind) = RecordedEventKind::from_event(event) {
+                self.record(kind);
+            }
+        }
+    }
+
+    /// Replays a recorded resize.
+    ///
+    /// Native windows are asked to resize themselves (the resize is then processed like any other),
+    /// headless windows are resized immediately.
+    fn replay_resize(&self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        if let Some(ref window) = self.window {
+            let _ = window.request_inner_size(PhysicalSize::new(width, height));
+        } else {
+            let scale_factor = self.scale_factor();
+            let physical_size = Size::new(width as f64, height as f64);
+            self.headless_size
+                .set(Size::new(physical_size.width / scale_factor, physical_size.height / scale_factor));
+            self.last_physical_size.set(physical_size);
+            self.layer.set_surface_size(physical_size);
+            self.root.mark_needs_relayout();
+            self.request_redraw();
+        }
+    }
+
+    /// Feeds recorded events to the window, respecting their timing.
+    async fn replay(&self, recording: &EventRecording) {
+        let (width, height) = recording.initial_size;
+        if self.last_physical_size.get() != Size::new(width as f64, height as f64) {
+            self.replay_resize(width, height);
+        }
+        let start = application::now();
+        for event in recording.events.iter() {
+            wait_until(start + event.time).await;
+            match event.kind {
+                RecordedEventKind::Resized { width, height } => self.replay_resize(width, height),
+                ref kind => {
+                    if let Some(event) = kind.to_event() {
+                        self.dispatch_input_event(event).await;
+                    }
+                }
+            }
+        }
+    }
+
     fn is_focused(&self, element: &Element) -> bool {
         self.focus == *element
     }
 
+    fn is_focus_visible(&self, element: &Element) -> bool {
+        self.focus_visible.get() && self.focus == *element
+    }
+
+    /// Returns whether the focused element is the specified element or one of its descendants.
+    fn is_focus_within(&self, element: &Element) -> bool {
+        self.focus
+            .upgrade()
+            .is_some_and(|focus| focus.ancestors_and_self().iter().any(|v| v.is_same(&*element.rc())))
+    }
+
+    /// Returns whether the visual is in the UI tree of this window.
+    fn is_in_tree(&self, visual: &dyn Visual) -> bool {
+        visual.ancestors_and_self()[0].is_same(&*self.root)
+    }
+
+    /// Returns the focused element, unless it has been removed from the tree.
+    fn focused_element(&self) -> Option<Rc<dyn Visual>> {
+        self.focus.upgrade().filter(|focus| self.is_in_tree(&**focus))
+    }
+
+    /// Called when an element is about to be removed from the tree.
+    ///
+    /// If the focus is inside the removed subtree, it is moved back to the most recently focused element that is
+    /// still in the tree, once the element has been removed (elements are often removed and re-inserted
+    /// elsewhere, in which case they keep the focus).
+    fn element_detached(&self, element: &Element) {
+        let Some(focus) = self.focus.upgrade() else {
+            return;
+        };
+        if !focus.ancestors_and_self().iter().any(|v| v.is_same(&*element.rc())) {
+            return;
+        }
+        let Some(this) = self.weak_this.upgrade() else {
+            return;
+        };
+        spawn(async move {
+            if !this.focus.upgrade().is_some_and(|f| f.is_same(&*focus)) || this.is_in_tree(&*focus) {
+                // the focus has moved, or the element was re-inserted
+                return;
+            }
+            this.focus.replace(None);
+            focus.send_event(&mut EventCtx::new(), &mut Event::FocusLost).await;
+            let restored = this.pop_focus_history();
+            if let Some(restored) = restored {
+                this.set_focus(Some(&restored)).await;
+            }
+        });
+    }
+
+    /// Returns the most recently focused element that is still in the tree.
+    fn pop_focus_history(&self) -> Option<Rc<dyn Visual>> {
+        let mut history = self.focus_history.borrow_mut();
+        while let Some(element) = history.pop() {
+            if let Some(element) = element.upgrade() {
+                if self.is_in_tree(&*element) {
+                    return Some(element);
+                }
+            }
+        }
+        None
+    }
+
     fn check_belongs_to_window(&self, element: &Element) {
         assert!(
             Weak::ptr_eq(&element.window.borrow().shared, &self.weak_this),
@@ -143,6 +416,14 @@ impl WindowInner {
         }
 
         let prev = self.focus.replace(element.map(|e| e.weak()));
+        if let Some(ref prev) = prev {
+            let mut history = self.focus_history.borrow_mut();
+            history.retain(|e| e.strong_count() > 0 && !Weak::ptr_eq(e, prev));
+            history.push(prev.clone());
+            if history.len() > FOCUS_HISTORY_LEN {
+                history.remove(0);
+            }
+        }
 
         // send focus gained/lost events
         if let Some(prev) = prev {
@@ -150,23 +431,221 @@ impl WindowInner {
                 self.dispatch_event(&*prev, &mut Event::FocusLost, false).await;
             }
         }
+        self.update_ime_allowed();
         if let Some(new) = self.focus.upgrade() {
             self.dispatch_event(&*new, &mut Event::FocusGained, false).await;
         }
     }
 
+    /// Enables the input method if the focused element accepts text input.
+    fn update_ime_allowed(&self) {
+        if let Some(ref window) = self.window {
+            let allowed = self.focus.upgrade().is_some_and(|focus| focus.accepts_text_input());
+            window.set_ime_allowed(allowed);
+        }
+    }
+
+    /// Called when an element starts or stops accepting text input.
+    fn accepts_text_input_changed(&self, element: &Element) {
+        if self.focus == *element {
+            self.update_ime_allowed();
+        }
+    }
+
+    /// Sets the area of the caret for the placement of the candidate window of the input method.
+    fn set_ime_cursor_area(&self, element: &Element, rect: Rect) {
+        if let Some(ref window) = self.window {
+            if self.focus == *element {
+                let rect = element.window_transform().transform_rect_bbox(rect);
+                window.set_ime_cursor_area(
+                    LogicalPosition::new(rect.x0, rect.y0),
+                    LogicalSize::new(rect.width(), rect.height()),
+                );
+            }
+        }
+    }
+
     fn set_pointer_capture(&self, element: &Element) {
         self.check_belongs_to_window(element);
         eprintln!("set_pointer_capture {}", element.name());
         self.pointer_capture.replace(Some(element.weak()));
     }
 
+    fn start_drag(&self, source: &Element, data: DragData, preview: Option<Rc<dyn Visual>>) {
+        self.check_belongs_to_window(source);
+        let session = DragSession {
+            source: source.weak(),
+            source_window: self.weak_this.clone(),
+            data,
+            preview,
+            current_window: None,
+            target: None,
+            accepted: false,
+        };
+        if DRAG_SESSION.replace(Some(session)).is_some() {
+            warn!("a drag-and-drop operation was already in progress");
+        }
+    }
+
+    /// Finds the window under the pointer during a drag-and-drop operation.
+    ///
+    /// Pointer events keep coming to the window where the drag started even when the pointer leaves it,
+    /// so `position` may be outside this window. Returns the window under the pointer and the position of the
+    /// pointer relative to it.
+    // FIXME: with overlapping windows, this picks the first one, regardless of the stacking order
+    fn drag_target_window(&self, position: Point) -> (Rc<WindowInner>, Point) {
+        let this = self.weak_this.upgrade().unwrap();
+        // headless windows have no position on the screen
+        let Some(window) = self.window.as_ref() else {
+            return (this, position);
+        };
+        let Ok(origin) = window.inner_position() else {
+            return (this, position);
+        };
+        if contains_physical_point(window, position) {
+            return (this, position);
+        }
+
+        let screen_position = Point::new(origin.x as f64 + position.x, origin.y as f64 + position.y);
+        let other = WINDOWS.with_borrow(|windows| {
+            windows.iter().filter_map(Weak::upgrade).find_map(|other| {
+                if Rc::ptr_eq(&other, &this) {
+                    return None;
+                }
+                let native = other.window.as_ref()?;
+                if native.is_visible() == Some(false) {
+                    return None;
+                }
+                let origin = native.inner_position().ok()?;
+                let local = Point::new(screen_position.x - origin.x as f64, screen_position.y - origin.y as f64);
+                if contains_physical_point(native, local) {
+                    Some((other, local))
+                } else {
+                    None
+                }
+            })
+        });
+        other.unwrap_or((this, position))
+    }
+
+    /// Moves the payload of the drag-and-drop operation in progress.
+    ///
+    /// Sends `DragLeave` and `DragEnter` events if the element under the pointer changed, then a `DragOver` event
+    /// to the element under the pointer to ask whether it accepts the payload.
+    async fn update_drag(&self, pointer: PointerEvent, position: Point) {
+        let (window, position) = self.drag_target_window(position);
+        let Some((data, prev_window, prev_target)) = DRAG_SESSION.with_borrow(|session| {
+            let session = session.as_ref()?;
+            let prev_window = session
+                .current_window
+                .as_ref()
+                .and_then(|(window, position)| Some((window.upgrade()?, *position)));
+            let prev_target = session.target.as_ref().and_then(Weak::upgrade).map(AnyVisual::from);
+            Some((session.data.clone(), prev_window, prev_target))
+        }) else {
+            return;
+        };
+
+        let target = window.root.do_hit_test(position).last().cloned();
+        let drag_event = |position: Point| DragEvent {
+            pointer: PointerEvent { position, ..pointer },
+            data: data.clone(),
+            accepted: false,
+        };
+
+        if prev_target != target {
+            if let (Some((prev_window, prev_position)), Some(prev)) = (&prev_window, &prev_target) {
+                let mut event = Event::DragLeave(drag_event(*prev_position));
+                prev_window.dispatch_event(&**prev, &mut event, true).await;
+            }
+            if let Some(ref target) = target {
+                let mut event = Event::DragEnter(drag_event(position));
+                window.dispatch_event(&**target, &mut event, true).await;
+            }
+        }
+
+        let mut accepted = false;
+        if let Some(ref target) = target {
+            let mut event = Event::DragOver(drag_event(position));
+            window.dispatch_event(&**target, &mut event, true).await;
+            if let Event::DragOver(ref drag_event) = event {
+                accepted = drag_event.accepted;
+            }
+        }
+
+        DRAG_SESSION.with_borrow_mut(|session| {
+            if let Some(session) = session {
+                session.current_window = Some((Rc::downgrade(&window), position));
+                session.target = target.map(|target| Rc::downgrade(&target.0));
+                session.accepted = accepted;
+            }
+        });
+
+        // repaint the preview
+        if let Some((prev_window, _)) = prev_window {
+            if !Rc::ptr_eq(&prev_window, &window) {
+                prev_window.request_redraw();
+            }
+        }
+        window.request_redraw();
+    }
+
+    /// Ends the drag-and-drop operation in progress.
+    ///
+    /// The payload is dropped on the element under the pointer if it accepted it and the operation wasn't
+    /// cancelled. Then the source element receives a `DragEnd` event telling whether the payload was dropped.
+    async fn finish_drag(&self, pointer: PointerEvent, cancelled: bool) {
+        let Some(session) = DRAG_SESSION.take() else {
+            return;
+        };
+
+        let mut dropped = false;
+        let window = session
+            .current_window
+            .as_ref()
+            .and_then(|(window, position)| Some((window.upgrade()?, *position)));
+        let target = session.target.as_ref().and_then(Weak::upgrade);
+        if let (Some((window, position)), Some(target)) = (window, target) {
+            let drag_event = DragEvent {
+                pointer: PointerEvent { position, ..pointer },
+                data: session.data.clone(),
+                accepted: session.accepted,
+            };
+            if session.accepted && !cancelled {
+                window.dispatch_event(&*target, &mut Event::Drop(drag_event), true).await;
+                dropped = true;
+            } else {
+                window.dispatch_event(&*target, &mut Event::DragLeave(drag_event), true).await;
+            }
+            // remove the preview
+            window.request_redraw();
+        }
+
+        if let Some(source_window) = session.source_window.upgrade() {
+            if let Some(source) = session.source.upgrade() {
+                let mut event = Event::DragEnd(DragEvent {
+                    pointer,
+                    data: session.data,
+                    accepted: dropped,
+                });
+                source_window.dispatch_event(&*source, &mut event, false).await;
+            }
+            source_window.pointer_capture.replace(None);
+        }
+    }
+
     /// Dispatches an event to a target visual in the UI tree.
     ///
-    /// It will first invoke the event handler of the target visual.
+    /// If some ancestors of the target have opted in with `set_capture_phase_events`, it first invokes their
+    /// event handlers, from the root down to the parent of the target (capture phase).
+    /// Then it invokes the event handler of the target visual.
     /// If the event is "bubbling", it will invoke the event handler of the parent visual,
     /// and so on until the root visual is reached.
-    async fn dispatch_event(&self, target: &dyn Visual, event: &mut Event, bubbling: bool) {
+    ///
+    /// Event handlers can stop the propagation at any point with `EventCtx::stop_propagation`.
+    /// Returns the final dispatch state, so that callers can check whether the default action
+    /// of the event should be performed.
+    async fn dispatch_event(&self, target: &dyn Visual, event: &mut Event, bubbling: bool) -> EventCtx {
         // get dispatch chain
         let chain = target.ancestors_and_self();
         assert!(
@@ -183,16 +662,37 @@ impl WindowInner {
             })
             .collect();
 
+        let mut ctx = EventCtx::new();
+        let ancestors = &chain[..chain.len() - 1];
+        let (target_transform, ancestor_transforms) = transforms.split_last().unwrap();
+
+        // capture phase, from the root down to the parent of the target
+        ctx.phase = EventPhase::Capturing;
+        for (visual, transform) in ancestors.iter().zip(ancestor_transforms.iter()) {
+            if !visual.receives_capture_phase_events() {
+                continue;
+            }
+            event.set_transform(transform);
+            visual.send_event(&mut ctx, event).await;
+            if ctx.is_propagation_stopped() {
+                return ctx;
+            }
+        }
+
+        ctx.phase = EventPhase::AtTarget;
+        event.set_transform(target_transform);
+        target.send_event(&mut ctx, event).await;
+
         if bubbling {
-            // dispatch the event, bubbling from the target up the root
-            for (visual, transform) in chain.iter().rev().zip(transforms.iter().rev()) {
+            // bubble from the parent of the target up the root
+            ctx.phase = EventPhase::Bubbling;
+            for (visual, transform) in ancestors.iter().zip(ancestor_transforms.iter()).rev() {
+                if ctx.is_propagation_stopped() {
+                    break;
+                }
                 event.set_transform(transform);
-                visual.send_event(event).await;
+                visual.send_event(&mut ctx, event).await;
             }
-        } else {
-            // dispatch the event to the target only
-            event.set_transform(transforms.last().unwrap());
-            target.send_event(event).await;
         }
 
         // handle repaint
@@ -201,32 +701,120 @@ impl WindowInner {
         //if self.root.needs_repaint() {
         //    self.window.request_redraw();
         //}
+        ctx
+    }
+
+    /// Returns the focused element and its ancestors, from the root, or only the root if nothing has the focus.
+    ///
+    /// This is the scope of commands: commands declared by these elements can be triggered by their shortcuts.
+    fn focus_chain(&self) -> Vec<Rc<dyn Visual>> {
+        match self.focused_element() {
+            Some(focus) => focus.ancestors_and_self(),
+            None => vec![self.root.clone()],
+        }
+    }
+
+    /// Asks the element that declared a command whether the command is enabled.
+    async fn query_command(&self, target: &dyn Visual, command: &'static str) -> bool {
+        let mut event = Event::CommandQuery(CommandQuery { command, enabled: true });
+        self.dispatch_event(target, &mut event, false).await;
+        matches!(event, Event::CommandQuery(CommandQuery { enabled: true, .. }))
+    }
+
+    /// Runs a command on the element that declared it, if the command is enabled.
+    ///
+    /// Returns whether the command was run.
+    async fn run_command(&self, target: &dyn Visual, command: &'static str) -> bool {
+        if !self.query_command(target, command).await {
+            return false;
+        }
+        self.dispatch_event(target, &mut Event::Command(command), false).await;
+        true
+    }
+
+    /// Runs the command bound to a key chord, if any.
+    ///
+    /// If several elements of the focus chain have a command bound to the key chord, the innermost element
+    /// with an enabled command wins. Returns whether a command was run.
+    async fn dispatch_shortcut(&self, event: &KeyboardEvent) -> bool {
+        for visual in self.focus_chain().iter().rev() {
+            let Some(command) =
+                visual.find_command(|c| c.effective_shortcut().is_some_and(|shortcut| shortcut.matches(event)))
+            else {
+                continue;
+            };
+            if self.run_command(&**visual, command.name).await {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Runs a command by name on the innermost element of the focus chain that declared it.
+    ///
+    /// Returns whether the command was run (i.e. some element declared it and it was enabled).
+    async fn execute_command(&self, name: &str) -> bool {
+        for visual in self.focus_chain().iter().rev() {
+            if let Some(command) = visual.find_command(|c| c.name == name) {
+                return self.run_command(&**visual, command.name).await;
+            }
+        }
+        false
+    }
+
+    /// Returns whether a command is declared in the focus chain and enabled.
+    async fn is_command_enabled(&self, name: &str) -> bool {
+        for visual in self.focus_chain().iter().rev() {
+            if let Some(command) = visual.find_command(|c| c.name == name) {
+                return self.query_command(&**visual, command.name).await;
+            }
+        }
+        false
     }
 
     /// Dispatches a keyboard event in the UI tree.
     ///
-    /// Currently, it just sends it to the focused element, or drops it if there's no focused element.
+    /// Key presses that match the shortcut of a command in the focus chain run the command. Otherwise, the event
+    /// is sent to the focused element, or dropped if there's no focused element.
     async fn dispatch_keyboard_event(&self, mut event: Event) {
-        if let Some(focus) = self.focus.upgrade() {
-            self.dispatch_event(&*focus, &mut event, true).await;
+        if let Event::KeyDown(ref ke) = event {
+            // Escape cancels drag-and-drop operations
+            if ke.key == Key::Escape && is_dragging() {
+                self.finish_drag(self.mouse_pointer_state(), true).await;
+                return;
+            }
+            if self.dispatch_shortcut(ke).await {
+                return;
+            }
         }
 
-        // TODO do this only if the event was not consumed
+        if let Some(focus) = self.focused_element() {
+            let ctx = self.dispatch_event(&*focus, &mut event, true).await;
+            if ctx.is_default_prevented() {
+                return;
+            }
+        }
 
         // Handle tab navigation
         match event {
             Event::KeyDown(ke) if ke.key == Key::Tab => {
-                if let Some(focus) = self.focus.upgrade() {
-                    // Go to next focusable element
-                    if let Some(next_focus) = focus.next_focusable_element() {
-                        self.set_focus(Some(&next_focus)).await;
-                    } else if let Some(next_focus) = self.root.next_focusable_element() {
-                        // cycle back to the first focusable element
-                        self.set_focus(Some(&next_focus)).await;
-                    } else {
-                        // no focusable elements
-                        self.set_focus(None).await;
+                let backwards = ke.modifiers.contains(keyboard_types::Modifiers::SHIFT);
+                let next_focus = match self.focused_element() {
+                    // Go to the next (or previous) focusable element in the focus scope
+                    Some(focus) if backwards => focus.previous_focusable_element(),
+                    Some(focus) => focus.next_focusable_element(),
+                    None => {
+                        let order = self.root.tab_order();
+                        if backwards {
+                            order.last().cloned()
+                        } else {
+                            order.first().cloned()
+                        }
                     }
+                };
+                if let Some(next_focus) = next_focus {
+                    self.focus_visible.set(true);
+                    self.set_focus(Some(&next_focus)).await;
                 }
             }
             _ => {}
@@ -249,6 +837,27 @@ impl WindowInner {
         hit_position: Point,
         //time: Duration,
     ) {
+        // During a drag-and-drop operation, pointer moves and releases drive the operation instead of being
+        // delivered to elements.
+        match event {
+            Event::PointerMove(pe) if is_dragging() => {
+                self.update_drag(pe, hit_position).await;
+                return;
+            }
+            Event::PointerUp(pe) if is_dragging() => {
+                self.update_drag(pe, hit_position).await;
+                self.finish_drag(pe, false).await;
+                self.pointer_capture.replace(None);
+                return;
+            }
+            _ => {}
+        }
+
+        // clicking hides the focus ring
+        if let Event::PointerDown(_) = event {
+            self.focus_visible.set(false);
+        }
+
         let mut input_state = self.input_state.borrow_mut();
 
         let hits = self.root.do_hit_test(hit_position);
@@ -268,6 +877,9 @@ impl WindowInner {
             self.pointer_capture.replace(None);
         }
 
+        let (pointer_type, pointer_id) = event
+            .pointer_event()
+            .map_or((PointerType::Mouse, MOUSE_POINTER_ID), |pe| (pe.pointer_type, pe.pointer_id));
         let p = PointerEvent {
             position: hit_position,
             modifiers: input_state.modifiers,
@@ -276,6 +888,8 @@ impl WindowInner {
             repeat_count: 0,
             transform: Default::default(),
             request_capture: false,
+            pointer_type,
+            pointer_id,
         };
 
         // convert hits to set
@@ -313,6 +927,104 @@ impl WindowInner {
         input_state.last_innermost_hit = innermost_hit;
     }
 
+    /// Dispatches an event for files dragged from outside the application.
+    ///
+    /// `FileHovered` and `FileDropped` are sent to the innermost element under the pointer, and
+    /// `FileHoverCancelled` to the element that received `FileHovered`.
+    /// Note that winit doesn't report pointer moves while files are dragged over the window, so the target is
+    /// determined from the last known position of the pointer.
+    async fn dispatch_file_drag_event(&self, mut event: Event, position: Point) {
+        let target = if let Event::FileHoverCancelled(_) = event {
+            self.file_hover_target.replace(None).and_then(|target| target.upgrade())
+        } else {
+            let target = self.root.do_hit_test(position).last().map(|hit| hit.0.clone());
+            let hover_target = match event {
+                Event::FileHovered(_) => target.as_ref().map(Rc::downgrade),
+                _ => None,
+            };
+            self.file_hover_target.replace(hover_target);
+            target
+        };
+        if let Some(target) = target {
+            self.dispatch_event(&*target, &mut event, true).await;
+        }
+    }
+
+    /// Returns the state of the mouse pointer, for events that aren't mouse button events.
+    fn mouse_pointer_state(&self) -> PointerEvent {
+        let input_state = self.input_state.borrow();
+        PointerEvent {
+            position: self.cursor_pos.get(),
+            modifiers: input_state.modifiers,
+            buttons: input_state.pointer_buttons,
+            button: None,
+            repeat_count: 0,
+            transform: Default::default(),
+            request_capture: false,
+            pointer_type: PointerType::Mouse,
+            pointer_id: MOUSE_POINTER_ID,
+        }
+    }
+
+    /// Converts a winit mouse wheel event to an Event.
+    fn convert_mouse_wheel(&self, delta: &MouseScrollDelta) -> Event {
+        // winit deltas are positive when scrolling up, we follow the DOM convention
+        let delta = match *delta {
+            MouseScrollDelta::LineDelta(x, y) => WheelDelta::Lines(-Vec2::new(x as f64, y as f64)),
+            MouseScrollDelta::PixelDelta(pos) => WheelDelta::Pixels(-self.logical_position(pos).to_vec2()),
+        };
+        Event::Wheel(WheelEvent {
+            pointer: self.mouse_pointer_state(),
+            delta,
+        })
+    }
+
+    /// Converts a winit touchpad gesture phase.
+    fn convert_gesture_phase(phase: TouchPhase) -> GesturePhase {
+        match phase {
+            TouchPhase::Started => GesturePhase::Started,
+            TouchPhase::Moved => GesturePhase::Moved,
+            TouchPhase::Ended => GesturePhase::Ended,
+            TouchPhase::Cancelled => GesturePhase::Cancelled,
+        }
+    }
+
+    /// Converts a position reported by winit (in physical pixels) to logical pixels, like all positions and
+    /// distances in events.
+    fn logical_position(&self, position: PhysicalPosition<f64>) -> Point {
+        let position = position.to_logical::<f64>(self.scale_factor());
+        Point::new(position.x, position.y)
+    }
+
+    /// Converts a winit touch event to a pointer event.
+    fn convert_touch(&self, touch: &Touch) -> Event {
+        let pressed = matches!(touch.phase, TouchPhase::Started | TouchPhase::Moved);
+        let pe = PointerEvent {
+            position: self.logical_position(touch.location),
+            modifiers: self.input_state.borrow().modifiers,
+            // a finger in contact is reported like a pressed left button
+            buttons: if pressed {
+                PointerButtons::new().with(PointerButton::LEFT)
+            } else {
+                PointerButtons::new()
+            },
+            button: match touch.phase {
+                TouchPhase::Moved => None,
+                _ => Some(PointerButton::LEFT),
+            },
+            repeat_count: 1,
+            transform: Default::default(),
+            request_capture: false,
+            pointer_type: PointerType::Touch,
+            pointer_id: touch.id,
+        };
+        match touch.phase {
+            TouchPhase::Started => Event::PointerDown(pe),
+            TouchPhase::Moved => Event::PointerMove(pe),
+            TouchPhase::Ended | TouchPhase::Cancelled => Event::PointerUp(pe),
+        }
+    }
+
     /// Converts a winit mouse event to an Event, and update internal state.
     fn convert_mouse_input(&self, device_id: DeviceId, button: MouseButton, state: ElementState) -> Option<Event> {
         let mut input_state = self.input_state.borrow_mut();
@@ -333,7 +1045,7 @@ impl WindowInner {
         } else {
             input_state.pointer_buttons.reset(button);
         }
-        let click_time = Instant::now();
+        let click_time = application::now();
 
         // determine the repeat count (double-click, triple-click, etc.) for button down event
         let repeat_count = match &mut input_state.last_click {
@@ -376,6 +1088,8 @@ impl WindowInner {
             repeat_count: repeat_count as u8,
             transform: Default::default(),
             request_capture: false,
+            pointer_type: PointerType::Mouse,
+            pointer_id: MOUSE_POINTER_ID,
         };
 
         let event = if state.is_pressed() {
@@ -400,6 +1114,9 @@ impl WindowInner {
             (Key::Alt, ElementState::Released) => input.modifiers.remove(keyboard_types::Modifiers::ALT),
             (Key::Meta, ElementState::Pressed) => input.modifiers.insert(keyboard_types::Modifiers::META),
             (Key::Meta, ElementState::Released) => input.modifiers.remove(keyboard_types::Modifiers::META),
+            // the Windows/Command key
+            (Key::Super, ElementState::Pressed) => input.modifiers.insert(keyboard_types::Modifiers::SUPER),
+            (Key::Super, ElementState::Released) => input.modifiers.remove(keyboard_types::Modifiers::SUPER),
             _ => {}
         }
 
@@ -433,15 +1150,19 @@ impl WindowInner {
     fn redirect_event_to_popup(&self, popup: &WindowInner, event: &WindowEvent) -> Option<WindowEvent> {
         // strategy: translate the event so that it appears to come from the popup window,
         // then directly invoke `dispatch_winit_input_event` on the popup window
+        let (Some(window), Some(popup_window)) = (&self.window, &popup.window) else {
+            return None;
+        };
+
         let self_client_area = {
-            let pos = self.window.inner_position().unwrap().cast();
-            let size = self.window.inner_size().cast();
+            let pos = window.inner_position().unwrap().cast();
+            let size = window.inner_size().cast();
             Rect::from_origin_size(Point::new(pos.x, pos.y), Size::new(size.width, size.height))
         };
 
         let popup_client_area = {
-            let pos = popup.window.inner_position().unwrap().cast();
-            let size = popup.window.inner_size().cast();
+            let pos = popup_window.inner_position().unwrap().cast();
+            let size = popup_window.inner_size().cast();
             Rect::from_origin_size(Point::new(pos.x, pos.y), Size::new(size.width, size.height))
         };
 
@@ -495,31 +1216,41 @@ impl WindowInner {
 
         match event {
             WindowEvent::CursorMoved { position, .. } => {
-                let pos = Point::new(position.x, position.y);
+                let pos = self.logical_position(*position);
                 //eprintln!("[{:?}] CursorMoved: {:?}", self.window.id(), pos);
                 self.cursor_pos.set(pos);
-                let modifiers = self.input_state.borrow().modifiers;
-                let buttons = self.input_state.borrow().pointer_buttons;
-                self.dispatch_pointer_event(
-                    Event::PointerMove(PointerEvent {
-                        position: pos,
-                        modifiers,
-                        buttons,
-                        button: None,
-                        repeat_count: 0,
-                        transform: Default::default(),
-                        request_capture: false,
-                    }),
-                    pos,
-                )
-                .await;
+                let event = Event::PointerMove(self.mouse_pointer_state());
+                self.record_event(&event);
+                self.dispatch_pointer_event(event, pos).await;
                 // force a redraw for the debug crosshair
-                self.window.request_redraw();
+                self.request_redraw();
             }
             WindowEvent::Touch(touch) => {
-                self.cursor_pos.set(Point::new(touch.location.x, touch.location.y));
+                let pos = self.logical_position(touch.location);
+                self.cursor_pos.set(pos);
+                let event = self.convert_touch(touch);
+                self.record_event(&event);
+                self.dispatch_pointer_event(event, pos).await;
                 // force a redraw for the debug crosshair
-                self.window.request_redraw();
+                self.request_redraw();
+            }
+            WindowEvent::TouchpadMagnify { delta, phase, .. } => {
+                let event = Event::Pinch(GestureEvent {
+                    pointer: self.mouse_pointer_state(),
+                    delta: *delta,
+                    phase: Self::convert_gesture_phase(*phase),
+                });
+                self.record_event(&event);
+                self.dispatch_pointer_event(event, self.cursor_pos.get()).await;
+            }
+            WindowEvent::TouchpadRotate { delta, phase, .. } => {
+                let event = Event::Rotate(GestureEvent {
+                    pointer: self.mouse_pointer_state(),
+                    delta: *delta as f64,
+                    phase: Self::convert_gesture_phase(*phase),
+                });
+                self.record_event(&event);
+                self.dispatch_pointer_event(event, self.cursor_pos.get()).await;
             }
             WindowEvent::KeyboardInput {
                 event,
@@ -527,9 +1258,20 @@ impl WindowInner {
                 is_synthetic,
             } => {
                 let converted_event = self.convert_keyboard_input(event);
+                self.record_event(&converted_event);
                 self.dispatch_keyboard_event(converted_event).await;
                 // for the debugging overlay
-                self.window.request_redraw();
+                self.request_redraw();
+            }
+            WindowEvent::Ime(ime) => {
+                let event = Event::Ime(match ime {
+                    Ime::Enabled => ImeEvent::Enabled,
+                    Ime::Preedit(text, cursor) => ImeEvent::Preedit(text.clone(), *cursor),
+                    Ime::Commit(text) => ImeEvent::Commit(text.clone()),
+                    Ime::Disabled => ImeEvent::Disabled,
+                });
+                self.record_event(&event);
+                self.dispatch_keyboard_event(event).await;
             }
             WindowEvent::MouseInput {
                 button,
@@ -537,13 +1279,44 @@ impl WindowInner {
                 device_id,
             } => {
                 if let Some(event) = self.convert_mouse_input(*device_id, *button, *state) {
+                    self.record_event(&event);
                     self.dispatch_pointer_event(event, self.cursor_pos.get()).await;
                 }
             }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let event = self.convert_mouse_wheel(delta);
+                self.record_event(&event);
+                self.dispatch_pointer_event(event, self.cursor_pos.get()).await;
+            }
+            WindowEvent::HoveredFile(path) => {
+                let event = Event::FileHovered(FileDragEvent {
+                    pointer: self.mouse_pointer_state(),
+                    path: path.clone(),
+                });
+                self.record_event(&event);
+                self.dispatch_file_drag_event(event, self.cursor_pos.get()).await;
+            }
+            WindowEvent::DroppedFile(path) => {
+                let event = Event::FileDropped(FileDragEvent {
+                    pointer: self.mouse_pointer_state(),
+                    path: path.clone(),
+                });
+                self.record_event(&event);
+                self.dispatch_file_drag_event(event, self.cursor_pos.get()).await;
+            }
+            WindowEvent::HoveredFileCancelled => {
+                let event = Event::FileHoverCancelled(self.mouse_pointer_state());
+                self.record_event(&event);
+                self.dispatch_file_drag_event(event, self.cursor_pos.get()).await;
+            }
             WindowEvent::CloseRequested => {
                 self.close_requested.emit(()).await;
             }
             WindowEvent::Resized(size) => {
+                self.record(RecordedEventKind::Resized {
+                    width: size.width,
+                    height: size.height,
+                });
                 self.resized.emit(*size).await;
                 if size.width != 0 && size.height != 0 {
                     // resize the compositor layer
@@ -552,6 +1325,7 @@ impl WindowInner {
                 }
             }
             WindowEvent::Focused(focused) => {
+                self.record(RecordedEventKind::Focused(*focused));
                 self.focus_changed.emit(*focused).await;
             }
             WindowEvent::RedrawRequested => {
@@ -567,6 +1341,8 @@ impl WindowInner {
                     repeat_count: 0,
                     transform: Default::default(),
                     request_capture: false,
+                    pointer_type: PointerType::Mouse,
+                    pointer_id: MOUSE_POINTER_ID,
                 };
                 //eprintln!("before events");
                 //this.root.send_event(&Event::PointerDown(dummy_pointer_event)).await;
@@ -576,9 +1352,146 @@ impl WindowInner {
         }
     }
 
+    /// Dispatches an input event that doesn't come from winit (e.g. synthetic events sent to a headless window).
+    async fn dispatch_input_event(&self, event: Event) {
+        self.record_event(&event);
+        match event {
+            Event::PointerMove(ref pe) | Event::PointerDown(ref pe) | Event::PointerUp(ref pe) => {
+                let position = pe.position;
+                self.cursor_pos.set(position);
+                {
+                    // keep the tracked input state consistent with the event
+                    let mut input_state = self.input_state.borrow_mut();
+                    input_state.modifiers = pe.modifiers;
+                    // touch contacts don't affect the state of the mouse buttons
+                    if pe.pointer_type == PointerType::Mouse {
+                        input_state.pointer_buttons = pe.buttons;
+                    }
+                }
+                self.dispatch_pointer_event(event, position).await;
+            }
+            Event::Wheel(WheelEvent { pointer: ref pe, .. })
+            | Event::Pinch(GestureEvent { pointer: ref pe, .. })
+            | Event::Rotate(GestureEvent { pointer: ref pe, .. }) => {
+                let position = pe.position;
+                self.cursor_pos.set(position);
+                self.dispatch_pointer_event(event, position).await;
+            }
+            Event::FileHovered(FileDragEvent { pointer: ref pe, .. })
+            | Event::FileDropped(FileDragEvent { pointer: ref pe, .. })
+            | Event::FileHoverCancelled(ref pe) => {
+                let position = pe.position;
+                self.cursor_pos.set(position);
+                self.dispatch_file_drag_event(event, position).await;
+            }
+            Event::KeyDown(ref ke) | Event::KeyUp(ref ke) => {
+                self.input_state.borrow_mut().modifiers = ke.modifiers;
+                self.dispatch_keyboard_event(event).await;
+            }
+            Event::Ime(_) => {
+                self.dispatch_keyboard_event(event).await;
+            }
+            Event::FocusGained => {
+                self.focus_changed.emit(true).await;
+            }
+            Event::FocusLost => {
+                self.focus_changed.emit(false).await;
+            }
+            event => {
+                warn!("unsupported input event: {:?}", event);
+            }
+        }
+    }
+
+    /// Updates the layout of the UI tree if necessary.
+    ///
+    /// # Arguments
+    ///
+    /// * size the logical size of the window
+    fn do_layout(&self, size: Size) {
+        loop {
+            if self.root.needs_relayout() {
+                let _geom = self.root.do_layout(&BoxConstraints::loose(size));
+            }
+
+            // Lay out the subtrees of relayout boundaries that weren't reached by the root layout.
+            let pending = mem::take(&mut *self.pending_relayouts.borrow_mut());
+            if pending.is_empty() {
+                break;
+            }
+            for element in pending {
+                let Some(element) = element.upgrade() else { continue };
+                if !element.needs_relayout() {
+                    continue;
+                }
+                let Some(constraints) = element.last_constraints() else { continue };
+                let old_size = element.geometry().size;
+                let geometry = element.do_layout(&constraints);
+                if geometry.size != old_size {
+                    // the boundary didn't keep its size, the parent must be laid out again
+                    if let Some(parent) = element.parent() {
+                        parent.mark_needs_relayout();
+                    }
+                }
+            }
+        }
+
+        // now that the geometry is up-to-date, scroll the requested rectangles into view
+        let requests = mem::take(&mut *self.pending_scroll_into_view.borrow_mut());
+        for (element, rect) in requests {
+            if let Some(element) = element.upgrade() {
+                element.scroll_into_view_now(rect);
+            }
+        }
+    }
+
+    /// Schedules a relayout of the subtree rooted at the specified relayout boundary.
+    fn schedule_relayout(&self, element: Weak<dyn Visual>) {
+        self.pending_relayouts.borrow_mut().push(element);
+        self.request_redraw();
+    }
+
+    /// Schedules the scrolling of a rectangle of an element into view after the next layout.
+    fn scroll_into_view(&self, element: Weak<dyn Visual>, rect: Rect) {
+        self.pending_scroll_into_view.borrow_mut().push((element, rect));
+        self.request_redraw();
+    }
+
+    /// Clears the surface with the window background and paints the UI tree on it.
+    fn paint_contents(&self, surface: &DrawableSurface, scale_factor: f64) {
+        // FIXME: only clear and flip invalid regions
+        surface.surface().canvas().clear(self.background.get().to_skia());
+        self.root.do_paint(surface, scale_factor);
+        self.paint_drag_preview(surface, scale_factor);
+        self.redraw_requested.set(false);
+    }
+
+    /// Paints the preview visual of the drag-and-drop operation in progress, if the pointer is over this window.
+    fn paint_drag_preview(&self, surface: &DrawableSurface, scale_factor: f64) {
+        let Some((preview, position)) = DRAG_SESSION.with_borrow(|session| {
+            let session = session.as_ref()?;
+            let (window, position) = session.current_window.as_ref()?;
+            if !Weak::ptr_eq(window, &self.weak_this) {
+                return None;
+            }
+            Some((session.preview.clone()?, *position))
+        }) else {
+            return;
+        };
+
+        preview.do_layout(&BoxConstraints::loose(Size::new(f64::INFINITY, f64::INFINITY)));
+        let mut ctx = PaintCtx {
+            scale_factor,
+            window_transform: Default::default(),
+            surface,
+        };
+        ctx.with_offset(position.to_vec2(), |ctx| paint_subtree(&*preview, ctx));
+    }
+
     fn do_redraw(&self) {
-        let scale_factor = self.window.scale_factor();
-        let physical_size = self.window.inner_size();
+        let window = self.native_window();
+        let scale_factor = window.scale_factor();
+        let physical_size = window.inner_size();
         if physical_size.width == 0 || physical_size.height == 0 {
             return;
         }
@@ -591,18 +1504,13 @@ impl WindowInner {
             //self.layer.set_surface_size(physical_size);
         }
 
-        if self.root.needs_relayout() {
-            let _geom = self.root.do_layout(&BoxConstraints::loose(size));
-        }
+        self.do_layout(size);
 
         let surface = self.layer.acquire_drawing_surface();
 
-        // FIXME: only clear and flip invalid regions
         {
             let mut skia_surface = surface.surface();
-            skia_surface.canvas().clear(self.background.get().to_skia());
-
-            self.root.do_paint(&surface, scale_factor);
+            self.paint_contents(&surface, scale_factor);
 
             // **** DEBUGGING ****
             draw_crosshair(skia_surface.canvas(), self.cursor_pos.get());
@@ -625,7 +1533,7 @@ impl WindowInner {
         // Now that we've rendered the first frame, we can reveal it.
         if self.hidden_before_first_draw.get() {
             self.hidden_before_first_draw.set(false);
-            self.window.set_visible(true);
+            window.set_visible(true);
         }
 
         //self.clear_change_flags(ChangeFlags::PAINT);
@@ -670,7 +1578,19 @@ impl Default for WeakWindow {
 impl WeakWindow {
     pub fn request_repaint(&self) {
         if let Some(shared) = self.shared.upgrade() {
-            shared.window.request_redraw();
+            shared.request_redraw();
+        }
+    }
+
+    /// Schedules a relayout of the subtree rooted at the specified relayout boundary.
+    ///
+    /// Returns `false` if the window doesn't exist anymore.
+    pub(crate) fn schedule_relayout(&self, element: Weak<dyn Visual>) -> bool {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.schedule_relayout(element);
+            true
+        } else {
+            false
         }
     }
 
@@ -686,6 +1606,78 @@ impl WeakWindow {
         }
     }
 
+    pub fn start_drag(&self, source: &Element, data: DragData, preview: Option<Rc<dyn Visual>>) {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.start_drag(source, data, preview);
+        }
+    }
+
+    /// Runs a command by name on the innermost element of the focus chain that declared it.
+    ///
+    /// Returns whether the command was run.
+    pub async fn execute_command(&self, name: &str) -> bool {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.execute_command(name).await
+        } else {
+            false
+        }
+    }
+
+    /// Returns whether a command is declared in the focus chain and enabled (e.g. to gray out menu items).
+    pub async fn is_command_enabled(&self, name: &str) -> bool {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.is_command_enabled(name).await
+        } else {
+            false
+        }
+    }
+
+    pub fn is_focus_visible(&self, element: &Element) -> bool {
+        self.shared
+            .upgrade()
+            .map(|shared| shared.is_focus_visible(element))
+            .unwrap_or(false)
+    }
+
+    pub fn is_focus_within(&self, element: &Element) -> bool {
+        self.shared
+            .upgrade()
+            .map(|shared| shared.is_focus_within(element))
+            .unwrap_or(false)
+    }
+
+    pub(crate) fn set_ime_cursor_area(&self, element: &Element, rect: Rect) {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.set_ime_cursor_area(element, rect);
+        }
+    }
+
+    /// Called by elements when they start or stop accepting text input.
+    pub(crate) fn accepts_text_input_changed(&self, element: &Element) {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.accepts_text_input_changed(element);
+        }
+    }
+
+    /// Schedules the scrolling of a rectangle of an element into view after the next layout.
+    ///
+    /// Returns `false` if the window is gone.
+    pub(crate) fn scroll_into_view(&self, element: Weak<dyn Visual>, rect: Rect) -> bool {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.scroll_into_view(element, rect);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Called by elements when they are about to be removed from the tree.
+    pub(crate) fn element_detached(&self, element: &Element) {
+        if let Some(shared) = self.shared.upgrade() {
+            shared.element_detached(element);
+        }
+    }
+
     /// Returns a reference to the currently focused element.
     pub fn is_focused(&self, element: &Element) -> bool {
         self.shared
@@ -728,12 +1720,23 @@ impl Window {
             // the window is initially invisible, we show it after the first frame is painted.
             let mut builder = winit::window::WindowBuilder::new()
                 .with_title(options.title)
-                .with_no_redirection_bitmap(true)
                 .with_decorations(options.decorations)
                 .with_visible(options.visible)
                 .with_inner_size(winit::dpi::LogicalSize::new(options.size.width, options.size.height));
+            #[cfg(windows)]
+            {
+                // we draw with DirectComposition, the window doesn't need its own surface
+                builder = builder.with_no_redirection_bitmap(true);
+            }
             if options.no_focus {
-                builder = builder.with_no_focus();
+                #[cfg(windows)]
+                {
+                    builder = builder.with_no_focus();
+                }
+                #[cfg(not(windows))]
+                {
+                    builder = builder.with_active(false);
+                }
             }
             if let Some(p) = options.position {
                 builder = builder.with_position(winit::dpi::LogicalPosition::new(p.x, p.y));
@@ -763,34 +1766,9 @@ impl Window {
         layer.wait_for_presentation();
 
         let window_id = window.id();
-        let shared = Rc::new_cyclic(|weak_this| WindowInner {
-            weak_this: weak_this.clone(),
-            close_requested: Handler::new(),
-            focus_changed: Handler::new(),
-            resized: Handler::new(),
-            root: root.rc(),
-            layer,
-            window,
-            hidden_before_first_draw: Cell::new(true),
-            cursor_pos: Cell::new(Default::default()),
-            last_physical_size: Cell::new(phy_size),
-            input_state: Default::default(),
-            pointer_capture: Default::default(),
-            focus: Default::default(),
-            background: Cell::new(options.background),
-            active_popup: RefCell::new(None),
-            last_kb_event: RefCell::new(None),
-        });
-
+        let scale_factor = window.scale_factor();
+        let shared = WindowInner::new(root, layer, Some(window), phy_size, scale_factor, options.background);
         application::register_window(window_id, shared.clone());
-
-        // Note: I don't really like the fact that elements themselves call back into the window
-        // to request a redraw. It would be better if the window could just listen for changes
-        // to the dirty flags. But since only one window is supposed to watch dirty flags,
-        // that would probably be an unnecessary complication.
-        let weak = Rc::downgrade(&shared);
-        root.set_parent_window(WeakWindow { shared: weak });
-
         Window { shared }
     }
 
@@ -798,6 +1776,16 @@ impl Window {
         self.shared.set_focus(element).await;
     }
 
+    /// Runs a command by name on the innermost element of the focus chain that declared it.
+    pub async fn execute_command(&self, name: &str) -> bool {
+        self.shared.execute_command(name).await
+    }
+
+    /// Returns whether a command is declared in the focus chain and enabled.
+    pub async fn is_command_enabled(&self, name: &str) -> bool {
+        self.shared.is_command_enabled(name).await
+    }
+
     pub fn as_weak(&self) -> WeakWindow {
         WeakWindow {
             shared: Rc::downgrade(&self.shared),
@@ -809,7 +1797,7 @@ impl Window {
     }
 
     pub fn raw_window_handle(&self) -> RawWindowHandle {
-        self.shared.window.window_handle().unwrap().as_raw()
+        self.shared.native_window().window_handle().unwrap().as_raw()
     }
 
     /// Waits for the window to be closed.
@@ -827,12 +1815,188 @@ impl Window {
         self.shared.focus_changed.wait().await
     }
 
+    /// Starts recording the input events received by the window.
+    pub fn start_recording(&self) {
+        self.shared.start_recording();
+    }
+
+    /// Stops recording input events and returns the recording, if one was in progress.
+    pub fn stop_recording(&self) -> Option<EventRecording> {
+        self.shared.stop_recording()
+    }
+
+    /// Replays recorded input events on this window.
+    pub async fn replay(&self, recording: &EventRecording) {
+        self.shared.replay(recording).await;
+    }
+
     /// Hides the window.
     pub fn hide(&self) {
-        self.shared.window.set_visible(false);
+        self.shared.native_window().set_visible(false);
     }
 
     pub fn is_hidden(&self) -> bool {
-        !self.shared.window.is_visible().unwrap()
+        !self.shared.native_window().is_visible().unwrap()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// Headless windows
+////////////////////////////////////////////////////////////////////////////////////////////////////
+
+/// Returns the size in physical pixels of a surface with the specified logical size.
+#[cfg(feature = "headless")]
+fn headless_physical_size(size: Size, scale_factor: f64) -> Size {
+    Size::new(
+        (size.width * scale_factor).ceil().max(1.0),
+        (size.height * scale_factor).ceil().max(1.0),
+    )
+}
+
+/// A window that is not backed by an operating system window.
+///
+/// It hosts a tree of visuals and runs layout, painting and event dispatch (focus, pointer capture,
+/// hover tracking) exactly like `Window`, but input events are synthetic `Event`s sent with `send_event`,
+/// and the UI is painted on a raster surface that can be read back with `render`.
+#[cfg(feature = "headless")]
+pub struct HeadlessWindow {
+    shared: Rc<WindowInner>,
+}
+
+#[cfg(feature = "headless")]
+impl HeadlessWindow {
+    /// Creates a new headless window.
+    ///
+    /// # Arguments
+    ///
+    /// * root the root element of the UI tree
+    /// * size logical size of the window
+    /// * scale_factor ratio of physical pixels to logical pixels
+    pub fn new(root: &Element, size: Size, scale_factor: f64) -> HeadlessWindow {
+        // Layers are created by the application backend
+        if AppGlobals::try_get().is_none() {
+            AppGlobals::new();
+        }
+        let physical_size = headless_physical_size(size, scale_factor);
+        let layer = Layer::new_surface(physical_size, ColorType::RGBA8888);
+        let shared = WindowInner::new(
+            root,
+            layer,
+            None,
+            physical_size,
+            scale_factor,
+            WindowOptions::default().background,
+        );
+        shared.headless_size.set(size);
+        HeadlessWindow { shared }
+    }
+
+    pub fn as_weak(&self) -> WeakWindow {
+        WeakWindow {
+            shared: Rc::downgrade(&self.shared),
+        }
+    }
+
+    /// Returns the logical size of the window.
+    pub fn size(&self) -> Size {
+        self.shared.headless_size.get()
+    }
+
+    /// Returns the scale factor of the window.
+    pub fn scale_factor(&self) -> f64 {
+        self.shared.scale_factor()
+    }
+
+    /// Resizes the window.
+    pub fn set_size(&self, size: Size) {
+        self.shared.headless_size.set(size);
+        self.update_surface_size();
+    }
+
+    /// Changes the scale factor of the window.
+    pub fn set_scale_factor(&self, scale_factor: f64) {
+        self.shared.scale_factor.set(scale_factor);
+        self.update_surface_size();
+    }
+
+    fn update_surface_size(&self) {
+        let physical_size = headless_physical_size(self.size(), self.shared.scale_factor());
+        self.shared.last_physical_size.set(physical_size);
+        self.shared.layer.set_surface_size(physical_size);
+        self.shared.root.mark_needs_relayout();
+    }
+
+    /// Sets the color used to clear the window before painting.
+    pub fn set_background(&self, color: Color) {
+        self.shared.background.set(color);
+        self.shared.request_redraw();
+    }
+
+    /// Sends an input event to the window.
+    ///
+    /// Pointer events (`PointerMove`, `PointerDown`, `PointerUp`) are hit-tested and dispatched to the
+    /// element under the pointer (or to the element capturing the pointer), keyboard events are sent
+    /// to the focused element, and `FocusGained`/`FocusLost` notify that the window itself gained or
+    /// lost focus. Positions are in logical pixels relative to the window.
+    pub async fn send_event(&self, event: Event) {
+        self.shared.dispatch_input_event(event).await;
+    }
+
+    /// Sets the element that has the keyboard focus.
+    pub async fn set_focus(&self, element: Option<&Element>) {
+        self.shared.set_focus(element).await;
+    }
+
+    /// Runs a command by name on the innermost element of the focus chain that declared it.
+    pub async fn execute_command(&self, name: &str) -> bool {
+        self.shared.execute_command(name).await
+    }
+
+    /// Returns whether a command is declared in the focus chain and enabled.
+    pub async fn is_command_enabled(&self, name: &str) -> bool {
+        self.shared.is_command_enabled(name).await
+    }
+
+    /// Starts recording the events sent to the window.
+    pub fn start_recording(&self) {
+        self.shared.start_recording();
+    }
+
+    /// Stops recording events and returns the recording, if one was in progress.
+    pub fn stop_recording(&self) -> Option<EventRecording> {
+        self.shared.stop_recording()
+    }
+
+    /// Replays recorded input events on this window.
+    ///
+    /// Recorded resizes change the size of the window.
+    pub async fn replay(&self, recording: &EventRecording) {
+        self.shared.replay(recording).await;
+    }
+
+    /// Returns the element that has the keyboard focus.
+    pub fn focused_element(&self) -> Option<Rc<dyn Visual>> {
+        self.shared.focus.upgrade()
+    }
+
+    /// Returns the element that is currently capturing the pointer.
+    pub fn pointer_capture(&self) -> Option<Rc<dyn Visual>> {
+        self.shared.pointer_capture.upgrade()
+    }
+
+    /// Returns whether an element has requested a repaint since the last call to `render`.
+    pub fn needs_redraw(&self) -> bool {
+        self.shared.redraw_requested.get()
+    }
+
+    /// Lays out and paints the UI tree, and returns the rendered image.
+    ///
+    /// The image has the physical size of the window.
+    pub fn render(&self) -> skia_safe::Image {
+        self.shared.do_layout(self.size());
+        let surface = self.shared.layer.acquire_drawing_surface();
+        self.shared.paint_contents(&surface, self.shared.scale_factor());
+        drop(surface);
+        self.shared.layer.snapshot()
     }
 }